@@ -0,0 +1,808 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+use json::Json;
+use lsblk;
+use lsblk::BlockMetadata;
+use lsblk::MajorMinor;
+use describe_block_type;
+use users;
+use BlockType;
+use Row;
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Column {
+	Name,
+	/// The kernel's name for the device, such as `dm-0` where NAME shows
+	/// the device-mapper name.
+	Kname,
+	Path,
+	MajMin,
+	Rm,
+	Hotplug,
+	Size,
+	Start,
+	Ro,
+	Rota,
+	Tran,
+	PhySec,
+	LogSec,
+	Alignment,
+	MinIo,
+	OptIo,
+	DiscGran,
+	DiscMax,
+	Dax,
+	Sched,
+	FsType,
+	Label,
+	Uuid,
+	FsMembers,
+	PartType,
+	PartTypeName,
+	PartLabel,
+	PartUuid,
+	Model,
+	Vendor,
+	Serial,
+	Wwn,
+	State,
+	Owner,
+	Group,
+	Mode,
+	Type,
+	Raid,
+	Mountpoint,
+	Mountpoints,
+	MountOptions,
+	/// Whether the filesystem is mounted read-only, which is separate from
+	/// whether the device itself is (RO).
+	FsRo,
+	BackFile,
+	Data,
+	Compr,
+	FsSize,
+	FsAvail,
+	FsUse,
+}
+
+/// Every column, in the order they are listed in help and error messages.
+pub const ALL_COLUMNS : &[Column] = &[
+	Column::Name,
+	Column::Kname,
+	Column::Path,
+	Column::MajMin,
+	Column::Rm,
+	Column::Hotplug,
+	Column::Size,
+	Column::Start,
+	Column::Ro,
+	Column::Rota,
+	Column::Tran,
+	Column::PhySec,
+	Column::LogSec,
+	Column::Alignment,
+	Column::MinIo,
+	Column::OptIo,
+	Column::DiscGran,
+	Column::DiscMax,
+	Column::Dax,
+	Column::Sched,
+	Column::FsType,
+	Column::Label,
+	Column::Uuid,
+	Column::FsMembers,
+	Column::PartType,
+	Column::PartTypeName,
+	Column::PartLabel,
+	Column::PartUuid,
+	Column::Model,
+	Column::Vendor,
+	Column::Serial,
+	Column::Wwn,
+	Column::State,
+	Column::Owner,
+	Column::Group,
+	Column::Mode,
+	Column::Type,
+	Column::Raid,
+	Column::Mountpoint,
+	Column::Mountpoints,
+	Column::MountOptions,
+	Column::FsRo,
+	Column::BackFile,
+	Column::Data,
+	Column::Compr,
+	Column::FsSize,
+	Column::FsAvail,
+	Column::FsUse,
+];
+
+pub const DEFAULT_COLUMNS : &[Column] = &[
+	Column::Name,
+	Column::MajMin,
+	Column::Rm,
+	Column::Size,
+	Column::Ro,
+	Column::FsType,
+	Column::Type,
+	Column::Mountpoint,
+];
+
+pub const FS_COLUMNS : &[Column] = &[
+	Column::Name,
+	Column::FsType,
+	Column::Label,
+	Column::Uuid,
+	Column::Mountpoint,
+];
+
+impl FromStr for Column {
+	type Err = String;
+	fn from_str(s : &str) -> Result<Column, String> {
+		ALL_COLUMNS.iter().cloned().find(|column| column.header().eq_ignore_ascii_case(s)).ok_or_else(|| {
+			let valid = ALL_COLUMNS.iter().map(|column| column.header()).collect::<Vec<_>>();
+			format!("unknown column '{}' (valid columns: {})", s, valid.join(", "))
+		})
+	}
+}
+
+/// Parses a comma-separated column list such as `NAME,SIZE,MOUNTPOINT`.
+pub fn parse_columns(list : &str) -> Result<Vec<Column>, String> {
+	list.split(',').map(Column::from_str).collect()
+}
+
+#[test]
+fn test_parse_columns() {
+	assert!(parse_columns("NAME,SIZE") == Ok(vec![Column::Name, Column::Size]));
+	assert!(parse_columns("mountpoint,maj:min,uuid") == Ok(vec![Column::Mountpoint, Column::MajMin, Column::Uuid]));
+	assert!(parse_columns("NAME,SIZE,BOGUS") ==
+		Err("unknown column 'BOGUS' (valid columns: NAME, KNAME, PATH, MAJ:MIN, RM, HOTPLUG, SIZE, START, RO, ROTA, TRAN, PHY-SEC, LOG-SEC, ALIGNMENT, MIN-IO, OPT-IO, DISC-GRAN, DISC-MAX, DAX, SCHED, FSTYPE, LABEL, UUID, FSMEMBERS, PARTTYPE, PARTTYPENAME, PARTLABEL, PARTUUID, MODEL, VENDOR, SERIAL, WWN, STATE, OWNER, GROUP, MODE, TYPE, RAID, MOUNTPOINT, MOUNTPOINTS, MOUNTOPTIONS, FSRO, BACK-FILE, DATA, COMPR, FSSIZE, FSAVAIL, FSUSE%)".to_owned()));
+	assert!(parse_columns("").is_err());
+}
+
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Align { Left, Right }
+
+/// Settings that change how a cell is rendered in the text output.
+#[derive(Default)]
+pub struct CellFormat {
+	pub bytes : bool,
+	/// Use powers of 1000 rather than 1024 for sizes.
+	pub si : bool,
+	/// Show RM and RO as `yes` or `no` rather than `1` or `0`.
+	pub verbose_flags : bool,
+}
+
+impl CellFormat {
+	pub fn size(&self, size : Option<u64>) -> String {
+		if self.bytes {
+			size.map_or_else(String::new, |size| size.to_string())
+		} else if self.si {
+			pretty_size(size, &SI_UNITS)
+		} else {
+			pretty_size(size, &BINARY_UNITS)
+		}
+	}
+
+	/// Like `size`, but a device without discard support, which reports
+	/// zero, shows `0B` as `lsblk` does.
+	fn discard_size(&self, size : Option<u64>) -> String {
+		match size {
+			Some(0) if !self.bytes => format!("{:>5}", "0B"),
+			size => self.size(size),
+		}
+	}
+
+	fn removable(&self, removable : Option<u64>) -> String {
+		if self.verbose_flags { verbose_flag(removable) } else { pretty_removable(removable) }.to_owned()
+	}
+
+	fn readonly(&self, readonly : Option<u64>) -> String {
+		if self.verbose_flags { verbose_flag(readonly) } else { pretty_readonly(readonly) }.to_owned()
+	}
+
+	/// A partition's first sector, or its offset in bytes with `-b`.
+	fn start(&self, start : Option<u64>) -> String {
+		if self.bytes {
+			format_number(start.map(|start| lsblk::bytes_from_sectors(start, 512)))
+		} else {
+			format_number(start)
+		}
+	}
+}
+
+#[test]
+fn test_discard_size() {
+	let format = CellFormat::default();
+	assert!(format.discard_size(Some(0)) == "   0B");
+	assert!(format.discard_size(Some(512)) == "  512");
+	assert!(format.discard_size(Some(4096)) == "   4K");
+	assert!(format.discard_size(Some(2147450880)) == "   2G");
+	assert!(format.discard_size(None) == "     ");
+
+	let format = CellFormat { bytes: true, ..CellFormat::default() };
+	assert!(format.discard_size(Some(0)) == "0");
+	assert!(format.discard_size(Some(4096)) == "4096");
+}
+
+fn metadata_field(row : &Row, field : fn(&BlockMetadata) -> &Option<String>) -> &str {
+	row.metadata.as_ref().and_then(|meta| field(meta).as_ref()).map_or("", |value| value)
+}
+
+/// A udev property that describes the hardware, such as `ID_MODEL`. Only
+/// disks show these, as udev copies them onto every partition too.
+fn disk_field(row : &Row, field : fn(&BlockMetadata) -> &Option<String>) -> String {
+	match row.row_type {
+		BlockType::Disk => unescape_udev(metadata_field(row, field)).trim().to_owned(),
+		_ => String::new(),
+	}
+}
+
+/// The short serial number, falling back to the longer vendor-prefixed one.
+fn serial(meta : &BlockMetadata) -> &Option<String> {
+	if meta.id_serial_short.is_some() {
+		&meta.id_serial_short
+	} else {
+		&meta.id_serial
+	}
+}
+
+/// The WWN with its vendor extension, falling back to the plain WWN.
+fn wwn(meta : &BlockMetadata) -> &Option<String> {
+	if meta.id_wwn_with_extension.is_some() {
+		&meta.id_wwn_with_extension
+	} else {
+		&meta.id_wwn
+	}
+}
+
+/// Decodes the `\xNN` escapes that udev uses for characters such as spaces
+/// in hardware strings. Malformed escapes are left as they are.
+fn unescape_udev(s : &str) -> String {
+	let mut unescaped = String::with_capacity(s.len());
+	let mut rest = s;
+	while let Some(i) = rest.find("\\x") {
+		unescaped.push_str(&rest[..i]);
+		let escape = rest.get(i+2..i+4).and_then(|hex| u8::from_str_radix(hex, 16).ok());
+		match escape {
+			Some(byte) if byte.is_ascii() => {
+				unescaped.push(byte as char);
+				rest = &rest[i+4..];
+			},
+			_ => {
+				unescaped.push_str("\\x");
+				rest = &rest[i+2..];
+			},
+		}
+	}
+	unescaped.push_str(rest);
+	unescaped
+}
+
+#[test]
+fn test_unescape_udev() {
+	assert!(unescape_udev("Samsung\\x20SSD\\x20850") == "Samsung SSD 850");
+	assert!(unescape_udev("Samsung_SSD_850") == "Samsung_SSD_850");
+	assert!(unescape_udev("ATA\\x20\\x20\\x20\\x20\\x20") == "ATA     ");
+	assert!(unescape_udev("a\\x2fb") == "a/b");
+	assert!(unescape_udev("a\\xzz") == "a\\xzz");
+	assert!(unescape_udev("a\\x2") == "a\\x2");
+	assert!(unescape_udev("").is_empty());
+}
+
+impl Column {
+	pub fn header(self) -> &'static str {
+		match self {
+			Column::Name => "NAME",
+			Column::Kname => "KNAME",
+			Column::Path => "PATH",
+			Column::MajMin => "MAJ:MIN",
+			Column::Rm => "RM",
+			Column::Hotplug => "HOTPLUG",
+			Column::Size => "SIZE",
+			Column::Start => "START",
+			Column::Ro => "RO",
+			Column::Rota => "ROTA",
+			Column::Tran => "TRAN",
+			Column::PhySec => "PHY-SEC",
+			Column::LogSec => "LOG-SEC",
+			Column::Alignment => "ALIGNMENT",
+			Column::MinIo => "MIN-IO",
+			Column::OptIo => "OPT-IO",
+			Column::DiscGran => "DISC-GRAN",
+			Column::DiscMax => "DISC-MAX",
+			Column::Dax => "DAX",
+			Column::Sched => "SCHED",
+			Column::FsType => "FSTYPE",
+			Column::Label => "LABEL",
+			Column::Uuid => "UUID",
+			Column::FsMembers => "FSMEMBERS",
+			Column::PartType => "PARTTYPE",
+			Column::PartTypeName => "PARTTYPENAME",
+			Column::PartLabel => "PARTLABEL",
+			Column::PartUuid => "PARTUUID",
+			Column::Model => "MODEL",
+			Column::Vendor => "VENDOR",
+			Column::Serial => "SERIAL",
+			Column::Wwn => "WWN",
+			Column::State => "STATE",
+			Column::Owner => "OWNER",
+			Column::Group => "GROUP",
+			Column::Mode => "MODE",
+			Column::Type => "TYPE",
+			Column::Raid => "RAID",
+			Column::Mountpoint => "MOUNTPOINT",
+			Column::Mountpoints => "MOUNTPOINTS",
+			Column::MountOptions => "MOUNTOPTIONS",
+			Column::FsRo => "FSRO",
+			Column::BackFile => "BACK-FILE",
+			Column::Data => "DATA",
+			Column::Compr => "COMPR",
+			Column::FsSize => "FSSIZE",
+			Column::FsAvail => "FSAVAIL",
+			Column::FsUse => "FSUSE%",
+		}
+	}
+
+	pub fn json_key(self) -> &'static str {
+		match self {
+			Column::Name => "name",
+			Column::Kname => "kname",
+			Column::Path => "path",
+			Column::MajMin => "maj:min",
+			Column::Rm => "rm",
+			Column::Hotplug => "hotplug",
+			Column::Size => "size",
+			Column::Start => "start",
+			Column::Ro => "ro",
+			Column::Rota => "rota",
+			Column::Tran => "tran",
+			Column::PhySec => "phy-sec",
+			Column::LogSec => "log-sec",
+			Column::Alignment => "alignment",
+			Column::MinIo => "min-io",
+			Column::OptIo => "opt-io",
+			Column::DiscGran => "disc-gran",
+			Column::DiscMax => "disc-max",
+			Column::Dax => "dax",
+			Column::Sched => "sched",
+			Column::FsType => "fstype",
+			Column::Label => "label",
+			Column::Uuid => "uuid",
+			Column::FsMembers => "fsmembers",
+			Column::PartType => "parttype",
+			Column::PartTypeName => "parttypename",
+			Column::PartLabel => "partlabel",
+			Column::PartUuid => "partuuid",
+			Column::Model => "model",
+			Column::Vendor => "vendor",
+			Column::Serial => "serial",
+			Column::Wwn => "wwn",
+			Column::State => "state",
+			Column::Owner => "owner",
+			Column::Group => "group",
+			Column::Mode => "mode",
+			Column::Type => "type",
+			Column::Raid => "raid",
+			Column::Mountpoint => "mountpoint",
+			Column::Mountpoints => "mountpoints",
+			Column::MountOptions => "mountoptions",
+			Column::FsRo => "fsro",
+			Column::BackFile => "back-file",
+			Column::Data => "data",
+			Column::Compr => "compr",
+			Column::FsSize => "fssize",
+			Column::FsAvail => "fsavail",
+			Column::FsUse => "fsuse%",
+		}
+	}
+
+	pub fn align(self) -> Align {
+		match self {
+			Column::Rm | Column::Hotplug | Column::Size | Column::Start | Column::Ro | Column::Rota | Column::FsRo => Align::Right,
+			Column::PhySec | Column::LogSec | Column::Alignment | Column::MinIo | Column::OptIo => Align::Right,
+			Column::DiscGran | Column::DiscMax | Column::Dax => Align::Right,
+			Column::Data | Column::Compr => Align::Right,
+			Column::FsSize | Column::FsAvail | Column::FsUse => Align::Right,
+			_ => Align::Left,
+		}
+	}
+
+	/// Whether the column is filled in from `statvfs` on the mountpoint.
+	pub fn needs_fs_usage(self) -> bool {
+		matches!(self, Column::FsSize | Column::FsAvail | Column::FsUse)
+	}
+
+	pub fn text(self, row : &Row, format : &CellFormat) -> String {
+		match self {
+			Column::Name => row.name.to_owned(),
+			Column::Kname => row.kname.to_owned(),
+			Column::Path => row.dev_path.as_ref().map_or_else(String::new, |path| path.display().to_string()),
+			Column::MajMin => format_major_minor(&row.majmin),
+			Column::Rm => format.removable(row.removable),
+			Column::Hotplug => pretty_flag(row.hotplug).to_owned(),
+			Column::Size => format.size(row.size),
+			Column::Start => format.start(row.start),
+			Column::Ro => format.readonly(row.readonly),
+			Column::Rota => pretty_flag(row.rotational).to_owned(),
+			Column::Tran => row.transport.clone().unwrap_or_default(),
+			Column::PhySec => format_number(row.physical_block_size),
+			Column::LogSec => format_number(row.logical_block_size),
+			Column::Alignment => row.alignment_offset.map_or_else(String::new, |offset| offset.to_string()),
+			Column::MinIo => format_number(row.minimum_io_size),
+			Column::OptIo => format_number(row.optimal_io_size.filter(|&size| size != 0)),
+			Column::DiscGran => format.discard_size(row.discard_granularity),
+			Column::DiscMax => format.discard_size(row.discard_max_bytes),
+			Column::Dax => pretty_flag(row.dax).to_owned(),
+			Column::Sched => row.scheduler.clone().unwrap_or_default(),
+			Column::FsType => metadata_field(row, |meta| &meta.id_fs_type).to_owned(),
+			Column::Label => metadata_field(row, |meta| &meta.id_fs_label).to_owned(),
+			Column::Uuid => metadata_field(row, |meta| &meta.id_fs_uuid).to_owned(),
+			Column::FsMembers => row.fs_members.join(","),
+			Column::PartType => metadata_field(row, |meta| &meta.id_part_entry_type).to_owned(),
+			Column::PartTypeName => describe_part_type(metadata_field(row, |meta| &meta.id_part_entry_type)).to_owned(),
+			Column::PartLabel => metadata_field(row, |meta| &meta.id_part_entry_name).to_owned(),
+			Column::PartUuid => metadata_field(row, |meta| &meta.id_part_entry_uuid).to_owned(),
+			Column::Model => disk_field(row, |meta| &meta.id_model),
+			Column::Vendor => disk_field(row, |meta| &meta.id_vendor),
+			Column::Serial => disk_field(row, serial),
+			Column::Wwn => disk_field(row, wwn),
+			Column::State => row.state.clone().unwrap_or_default(),
+			Column::Owner => row.node.map_or_else(String::new, |node| users::user_name(node.uid)),
+			Column::Group => row.node.map_or_else(String::new, |node| users::group_name(node.gid)),
+			Column::Mode => row.node.map_or_else(String::new, |node| format_mode(node.mode)),
+			Column::Type => describe_block_type(row.row_type).to_owned(),
+			Column::Raid => row.raid_level.clone().unwrap_or_default(),
+			Column::Mountpoint => row.mountpoint.to_owned(),
+			Column::Mountpoints => row.mountpoints.join("\n"),
+			Column::MountOptions => row.mount_options.to_owned(),
+			Column::FsRo => format.readonly(mount_readonly(&row.mount_options)),
+			Column::BackFile => row.backing_file.clone().unwrap_or_default(),
+			Column::Data => row.zram.map_or_else(String::new, |zram| format.size(Some(zram.data))),
+			Column::Compr => row.zram.map_or_else(String::new, |zram| format.size(Some(zram.compressed))),
+			Column::FsSize => row.fs_usage.map_or_else(String::new, |usage| format.size(Some(usage.size))),
+			Column::FsAvail => row.fs_usage.map_or_else(String::new, |usage| format.size(Some(usage.avail))),
+			Column::FsUse => row.fs_usage.map_or_else(String::new, |usage| format!("{}%", usage.percent_used())),
+		}
+	}
+
+	/// Orders two rows by this column: numerically for sizes and device
+	/// numbers, with missing values last, and by the displayed text
+	/// otherwise.
+	pub fn compare(self, a : &Row, b : &Row) -> Ordering {
+		match self {
+			Column::MajMin => (a.majmin.major, a.majmin.minor).cmp(&(b.majmin.major, b.majmin.minor)),
+			Column::Size => compare_missing_last(a.size, b.size),
+			Column::Start => compare_missing_last(a.start, b.start),
+			Column::PhySec => compare_missing_last(a.physical_block_size, b.physical_block_size),
+			Column::LogSec => compare_missing_last(a.logical_block_size, b.logical_block_size),
+			Column::Alignment => compare_missing_last(a.alignment_offset, b.alignment_offset),
+			Column::MinIo => compare_missing_last(a.minimum_io_size, b.minimum_io_size),
+			Column::OptIo => compare_missing_last(a.optimal_io_size, b.optimal_io_size),
+			Column::DiscGran => compare_missing_last(a.discard_granularity, b.discard_granularity),
+			Column::DiscMax => compare_missing_last(a.discard_max_bytes, b.discard_max_bytes),
+			Column::Data => compare_missing_last(a.zram.map(|zram| zram.data), b.zram.map(|zram| zram.data)),
+			Column::Compr => compare_missing_last(a.zram.map(|zram| zram.compressed), b.zram.map(|zram| zram.compressed)),
+			Column::FsSize => compare_missing_last(a.fs_usage.map(|usage| usage.size), b.fs_usage.map(|usage| usage.size)),
+			Column::FsAvail => compare_missing_last(a.fs_usage.map(|usage| usage.avail), b.fs_usage.map(|usage| usage.avail)),
+			Column::FsUse => compare_missing_last(a.fs_usage.map(|usage| usage.percent_used()), b.fs_usage.map(|usage| usage.percent_used())),
+			_ => {
+				let format = CellFormat::default();
+				self.text(a, &format).cmp(&self.text(b, &format))
+			},
+		}
+	}
+
+	pub fn json(self, row : &Row) -> Json {
+		match self {
+			Column::Name => Json::text(&row.name),
+			Column::Kname => Json::text(&row.kname),
+			Column::Path => Json::text(&row.dev_path.as_ref().map_or_else(String::new, |path| path.display().to_string())),
+			Column::MajMin => Json::String(row.majmin.to_string()),
+			Column::Rm => Json::flag(row.removable),
+			Column::Hotplug => Json::flag(row.hotplug),
+			Column::Size => Json::number(row.size),
+			Column::Start => Json::number(row.start),
+			Column::Ro => Json::flag(row.readonly),
+			Column::Rota => Json::flag(row.rotational),
+			Column::Tran => Json::text(row.transport.as_ref().map_or("", |tran| tran)),
+			Column::PhySec => Json::number(row.physical_block_size),
+			Column::LogSec => Json::number(row.logical_block_size),
+			Column::Alignment => Json::integer(row.alignment_offset),
+			Column::MinIo => Json::number(row.minimum_io_size),
+			Column::OptIo => Json::number(row.optimal_io_size),
+			Column::DiscGran => Json::number(row.discard_granularity),
+			Column::DiscMax => Json::number(row.discard_max_bytes),
+			Column::Dax => Json::flag(row.dax),
+			Column::Sched => Json::text(row.scheduler.as_ref().map_or("", |sched| sched)),
+			Column::FsType => Json::text(metadata_field(row, |meta| &meta.id_fs_type)),
+			Column::Label => Json::text(metadata_field(row, |meta| &meta.id_fs_label)),
+			Column::Uuid => Json::text(metadata_field(row, |meta| &meta.id_fs_uuid)),
+			Column::FsMembers => Json::Array(row.fs_members.iter().map(|member| Json::text(member)).collect()),
+			Column::PartType => Json::text(metadata_field(row, |meta| &meta.id_part_entry_type)),
+			Column::PartTypeName => Json::text(describe_part_type(metadata_field(row, |meta| &meta.id_part_entry_type))),
+			Column::PartLabel => Json::text(metadata_field(row, |meta| &meta.id_part_entry_name)),
+			Column::PartUuid => Json::text(metadata_field(row, |meta| &meta.id_part_entry_uuid)),
+			Column::Model => Json::text(&disk_field(row, |meta| &meta.id_model)),
+			Column::Vendor => Json::text(&disk_field(row, |meta| &meta.id_vendor)),
+			Column::Serial => Json::text(&disk_field(row, serial)),
+			Column::Wwn => Json::text(&disk_field(row, wwn)),
+			Column::State => Json::text(row.state.as_ref().map_or("", |state| state)),
+			Column::Owner | Column::Group | Column::Mode => Json::text(&self.text(row, &CellFormat::default())),
+			Column::Type => Json::text(describe_block_type(row.row_type)),
+			Column::Raid => Json::text(row.raid_level.as_ref().map_or("", |level| level)),
+			Column::Mountpoint => Json::text(&row.mountpoint),
+			Column::Mountpoints => Json::Array(row.mountpoints.iter().map(|mountpoint| Json::text(mountpoint)).collect()),
+			Column::MountOptions => Json::text(&row.mount_options),
+			Column::FsRo => Json::flag(mount_readonly(&row.mount_options)),
+			Column::BackFile => Json::text(row.backing_file.as_ref().map_or("", |file| file)),
+			Column::Data => Json::number(row.zram.map(|zram| zram.data)),
+			Column::Compr => Json::number(row.zram.map(|zram| zram.compressed)),
+			Column::FsSize => Json::number(row.fs_usage.map(|usage| usage.size)),
+			Column::FsAvail => Json::number(row.fs_usage.map(|usage| usage.avail)),
+			Column::FsUse => row.fs_usage.map_or(Json::Null, |usage| Json::String(format!("{}%", usage.percent_used()))),
+		}
+	}
+}
+
+/// Well-known GPT partition type GUIDs and their names.
+const PART_TYPES : &[(&str, &str)] = &[
+	("c12a7328-f81f-11d2-ba4b-00a0c93ec93b", "EFI System"),
+	("21686148-6449-6e6f-744e-656564454649", "BIOS boot"),
+	("0fc63daf-8483-4772-8e79-3d69d8477de4", "Linux filesystem"),
+	("0657fd6d-a4ab-43c4-84e5-0933c84b4f4f", "Linux swap"),
+	("e6d6d379-f507-44c2-a23c-238f2a3df928", "Linux LVM"),
+	("a19d880f-05fc-4d3b-a006-743f0f84911e", "Linux RAID"),
+	("ebd0a0a2-b9e5-4433-87c0-68b6b72699c7", "Microsoft basic data"),
+	("e3c9e316-0b5c-4db8-817d-f92df00215ae", "Microsoft reserved"),
+];
+
+/// The name of a partition type, or the type itself if it is not a GUID in
+/// `PART_TYPES`.
+fn describe_part_type(part_type : &str) -> &str {
+	PART_TYPES.iter()
+		.find(|&&(guid, _)| guid.eq_ignore_ascii_case(part_type))
+		.map_or(part_type, |&(_, name)| name)
+}
+
+#[test]
+fn test_describe_part_type() {
+	assert!(describe_part_type("c12a7328-f81f-11d2-ba4b-00a0c93ec93b") == "EFI System");
+	assert!(describe_part_type("0FC63DAF-8483-4772-8E79-3D69D8477DE4") == "Linux filesystem");
+	assert!(describe_part_type("0657fd6d-a4ab-43c4-84e5-0933c84b4f4f") == "Linux swap");
+	assert!(describe_part_type("ebd0a0a2-b9e5-4433-87c0-68b6b72699c7") == "Microsoft basic data");
+	assert!(describe_part_type("4f68bce3-e8cd-4db1-96e7-fbcaf984b709") == "4f68bce3-e8cd-4db1-96e7-fbcaf984b709");
+	assert!(describe_part_type("0x83") == "0x83");
+	assert!(describe_part_type("").is_empty());
+}
+
+fn format_number(n : Option<u64>) -> String {
+	n.map_or_else(String::new, |n| n.to_string())
+}
+
+fn format_major_minor(majmin: &MajorMinor) -> String {
+	format!("{:>3}:{:<3}", majmin.major, majmin.minor)
+}
+
+#[test]
+fn test_format_major_minor() {
+	assert!(format_major_minor(&MajorMinor { major:   1, minor:   0 }) == "  1:0  ");
+	assert!(format_major_minor(&MajorMinor { major:  10, minor:   0 }) == " 10:0  ");
+	assert!(format_major_minor(&MajorMinor { major:   1, minor:  20 }) == "  1:20 ");
+	assert!(format_major_minor(&MajorMinor { major: 100, minor:  20 }) == "100:20 ");
+	assert!(format_major_minor(&MajorMinor { major: 100, minor: 200 }) == "100:200");
+	assert!(format_major_minor(&MajorMinor { major: 259, minor:   3 }) == "259:3  ");
+}
+
+/// Orders two optional values with `None` after every value.
+fn compare_missing_last<T: Ord>(a : Option<T>, b : Option<T>) -> Ordering {
+	match (a, b) {
+		(Some(a), Some(b)) => a.cmp(&b),
+		(a, b) => a.is_none().cmp(&b.is_none()),
+	}
+}
+
+#[test]
+fn test_compare_missing_last() {
+	assert!(compare_missing_last(Some(1), Some(2)) == Ordering::Less);
+	assert!(compare_missing_last(Some(2), Some(2)) == Ordering::Equal);
+	assert!(compare_missing_last(Some(1), None) == Ordering::Less);
+	assert!(compare_missing_last(None, Some(1)) == Ordering::Greater);
+	assert!(compare_missing_last::<u64>(None, None) == Ordering::Equal);
+}
+
+/// Whether a mount's options include `ro`, as a flag like the device's own
+/// `ro` attribute. Unmounted devices have no options, and give `None`.
+fn mount_readonly(options : &str) -> Option<u64> {
+	if options.is_empty() {
+		None
+	} else {
+		Some(options.split(',').any(|option| option == "ro") as u64)
+	}
+}
+
+#[test]
+fn test_mount_readonly() {
+	assert!(mount_readonly("ro,relatime") == Some(1));
+	assert!(mount_readonly("rw,noatime,errors=remount-ro") == Some(0));
+	assert!(mount_readonly("").is_none());
+}
+
+fn pretty_flag(flag : Option<u64>) -> &'static str {
+	match flag {
+		Some(0) => " 0",
+		Some(_) => " 1",
+		None => "  ",
+	}
+}
+
+/// Formats a device node's type and permissions as `ls -l` does, such as
+/// `brw-rw----`.
+fn format_mode(mode : u32) -> String {
+	let file_type = match mode & 0o170000 {
+		0o060000 => 'b',
+		0o020000 => 'c',
+		0o040000 => 'd',
+		0o120000 => 'l',
+		_ => '-',
+	};
+	let mut text = file_type.to_string();
+	for (i, special) in [(0o4000, 's'), (0o2000, 's'), (0o1000, 't')].iter().enumerate() {
+		let bits = mode >> (6 - 3 * i);
+		text.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+		text.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+		text.push(match (mode & special.0 != 0, bits & 0o1 != 0) {
+			(true, true) => special.1,
+			(true, false) => special.1.to_ascii_uppercase(),
+			(false, true) => 'x',
+			(false, false) => '-',
+		});
+	}
+	text
+}
+
+#[test]
+fn test_format_mode() {
+	assert!(format_mode(0o060660) == "brw-rw----");
+	assert!(format_mode(0o020666) == "crw-rw-rw-");
+	assert!(format_mode(0o100644) == "-rw-r--r--");
+	assert!(format_mode(0o041777) == "drwxrwxrwt");
+	assert!(format_mode(0o106644) == "-rwSr-Sr--");
+}
+
+fn pretty_removable(removable : Option<u64>) -> &'static str {
+	pretty_flag(removable)
+}
+
+/// A flag spelled out for `--verbose-flags`.
+fn verbose_flag(flag : Option<u64>) -> &'static str {
+	match flag {
+		Some(0) => "no",
+		Some(_) => "yes",
+		None => "-",
+	}
+}
+
+#[test]
+fn test_verbose_flag() {
+	assert!(verbose_flag(None) == "-");
+	assert!(verbose_flag(Some(0)) == "no");
+	assert!(verbose_flag(Some(1)) == "yes");
+	assert!(verbose_flag(Some(2)) == "yes");
+}
+
+/// A base and the suffixes for each successive power of it.
+struct Units {
+	base : u64,
+	suffixes : &'static [&'static str],
+}
+
+const BINARY_UNITS : Units = Units {
+	base: 1024,
+	suffixes: &["K", "M", "G", "T", "P", "E"],
+};
+
+const SI_UNITS : Units = Units {
+	base: 1000,
+	suffixes: &["KB", "MB", "GB", "TB", "PB", "EB"],
+};
+
+/// Formats a size already scaled into its unit. Values below ten keep one
+/// decimal place, except that a trailing `.0` is dropped so that exact
+/// powers of the base print as `1M` rather than `1.0M`.
+fn pretty_units(n : f64, suffix : &str) -> String {
+	let digits = if n < 10.0 { format!("{:.1}", n) } else { format!("{:.0}", n) };
+	let digits = digits.trim_end_matches(".0");
+	format!("{:>5}", format!("{}{}", digits, suffix))
+}
+
+/// Formats a size in bytes using the largest unit that it is at least one
+/// of, as `lsblk` does.
+fn pretty_size(size : Option<u64>, units : &Units) -> String {
+	match size {
+		Some(size) => {
+			let base = units.base as f64;
+			let mut n = size as f64;
+			let mut unit = None;
+			for i in 0..units.suffixes.len() {
+				if n < base {
+					break;
+				}
+				n /= base;
+				unit = Some(i);
+			}
+			// A value just short of the next unit rounds up to a whole one of
+			// it, so a byte less than 1M is 1M rather than 1024K.
+			if let Some(i) = unit {
+				if n.round() >= base && i+1 < units.suffixes.len() {
+					n /= base;
+					unit = Some(i+1);
+				}
+			}
+			match unit {
+				Some(i) => pretty_units(n, units.suffixes[i]),
+				None => format!("{:>5}", size),
+			}
+		},
+		None => "     ".into(),
+	}
+}
+
+#[test]
+fn test_pretty_size() {
+	let pretty_size = |size| pretty_size(size, &BINARY_UNITS);
+	assert!("     " == pretty_size(None));
+	assert!("    0" == pretty_size(Some(0)));
+	assert!(" 1000" == pretty_size(Some(1000)));
+	assert!(" 1023" == pretty_size(Some(1023)));
+	assert!("   1K" == pretty_size(Some(1024)));
+	assert!(" 1.5K" == pretty_size(Some(1536)));
+	assert!("  10K" == pretty_size(Some(10240)));
+	assert!("  57M" == pretty_size(Some(60063744)));
+	assert!("   4G" == pretty_size(Some(4292870144)));
+	assert!("  28G" == pretty_size(Some(30063722496)));
+	assert!("  32G" == pretty_size(Some(34359738368)));
+	assert!("  16E" == pretty_size(Some(u64::MAX)));
+}
+
+#[test]
+fn test_pretty_size_boundaries() {
+	let pretty_size = |size| pretty_size(size, &BINARY_UNITS);
+	assert!("   1M" == pretty_size(Some(1048575)));
+	assert!("   1G" == pretty_size(Some(1073741823)));
+	assert!("1023K" == pretty_size(Some(1023 * 1024)));
+	assert!("   1M" == pretty_size(Some(1024u64.pow(2))));
+	assert!("   1G" == pretty_size(Some(1024u64.pow(3))));
+	assert!("   1T" == pretty_size(Some(1024u64.pow(4))));
+	assert!("   1P" == pretty_size(Some(1024u64.pow(5))));
+	assert!("   1E" == pretty_size(Some(1024u64.pow(6))));
+	assert!(" 1.1M" == pretty_size(Some(1024u64.pow(2) + 100 * 1024)));
+}
+
+#[test]
+fn test_pretty_size_si() {
+	let pretty_size = |size| pretty_size(size, &SI_UNITS);
+	assert!("  999" == pretty_size(Some(999)));
+	assert!("  1KB" == pretty_size(Some(1000)));
+	assert!("  1KB" == pretty_size(Some(1024)));
+	assert!(" 60MB" == pretty_size(Some(60063744)));
+	assert!("  1GB" == pretty_size(Some(1000u64.pow(3))));
+	assert!(" 34GB" == pretty_size(Some(34359738368)));
+	assert!("  1MB" == pretty_size(Some(999999)));
+}
+
+fn pretty_readonly(readonly: Option<u64>) -> &'static str {
+	pretty_flag(readonly)
+}
+
+#[test]
+fn test_pretty_readonly() {
+	assert!("  " == pretty_readonly(None));
+	assert!(" 0" == pretty_readonly(Some(0)));
+	assert!(" 1" == pretty_readonly(Some(1)));
+	assert!(" 1" == pretty_readonly(Some(2)));
+	assert!(" 1" == pretty_readonly(Some(1234)));
+}
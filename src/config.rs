@@ -0,0 +1,188 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use column;
+use column::Column;
+use filter;
+use options::Options;
+
+/// How sizes are shown when neither `--bytes` nor `--si` is given.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Units { Binary, Si, Bytes }
+
+fn parse_units(units : &str) -> Result<Units, String> {
+	match units {
+		"binary" => Ok(Units::Binary),
+		"si" => Ok(Units::Si),
+		"bytes" => Ok(Units::Bytes),
+		_ => Err(format!("invalid units '{}' (valid units: binary, si, bytes)", units)),
+	}
+}
+
+/// Defaults read from the config file and the environment, used wherever
+/// the command line does not say otherwise.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub struct Config {
+	pub columns : Option<Vec<Column>>,
+	pub units : Option<Units>,
+	pub exclude : Option<Vec<u32>>,
+	pub include : Option<Vec<u32>>,
+	pub fstypes : Option<Vec<String>>,
+	pub exclude_fstypes : Option<Vec<String>>,
+}
+
+/// Removes the quotes from a TOML string, so that `columns = "NAME,SIZE"`
+/// and `columns = NAME,SIZE` mean the same.
+fn unquote(value : &str) -> &str {
+	if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+		&value[1..value.len()-1]
+	} else {
+		value
+	}
+}
+
+/// Parses a config file, which is a small subset of TOML: one
+/// `key = "value"` setting per line, with `#` starting a comment line.
+pub fn parse_config(text : &str) -> Result<Config, String> {
+	let mut config = Config::default();
+	for (n, line) in text.lines().enumerate() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') {
+			continue;
+		}
+		let (key, value) = line.split_once('=').ok_or_else(|| format!("line {}: expected 'key = value'", n+1))?;
+		let value = unquote(value.trim());
+		let result = match key.trim() {
+			"columns" => column::parse_columns(value).map(|columns| config.columns = Some(columns)),
+			"units" => parse_units(value).map(|units| config.units = Some(units)),
+			"exclude" => filter::parse_majors(value).map(|majors| config.exclude = Some(majors)),
+			"include" => filter::parse_majors(value).map(|majors| config.include = Some(majors)),
+			"fstype" => {
+				config.fstypes = Some(filter::parse_fstypes(value));
+				Ok(())
+			},
+			"exclude-fstype" => {
+				config.exclude_fstypes = Some(filter::parse_fstypes(value));
+				Ok(())
+			},
+			key => Err(format!("unknown setting '{}'", key)),
+		};
+		result.map_err(|err| format!("line {}: {}", n+1, err))?;
+	}
+	if config.include.is_some() && config.exclude.is_some() {
+		return Err("include and exclude are mutually exclusive".to_owned());
+	}
+	Ok(config)
+}
+
+#[test]
+fn test_parse_config() {
+	assert!(parse_config("") == Ok(Config::default()));
+	let config = parse_config("\
+		# Defaults for lsblk\n\
+		columns = \"NAME,SIZE,MOUNTPOINT\"\n\
+		\n\
+		units = si\n\
+		exclude = \"7, 1\"\n\
+		exclude-fstype = \"squashfs\"\n").unwrap();
+	assert!(config == Config {
+		columns: Some(vec![Column::Name, Column::Size, Column::Mountpoint]),
+		units: Some(Units::Si),
+		exclude: Some(vec![7, 1]),
+		exclude_fstypes: Some(vec!["squashfs".to_owned()]),
+		..Config::default()
+	});
+	assert!(parse_config("columns = NAME,NOPE").err().unwrap().starts_with("line 1: unknown column 'NOPE'"));
+	assert!(parse_config("\nunits = kb").err() == Some("line 2: invalid units 'kb' (valid units: binary, si, bytes)".to_owned()));
+	assert!(parse_config("colour = always").err() == Some("line 1: unknown setting 'colour'".to_owned()));
+	assert!(parse_config("bytes").err() == Some("line 1: expected 'key = value'".to_owned()));
+	assert!(parse_config("include = 8\nexclude = 7").is_err());
+}
+
+/// `$XDG_CONFIG_HOME/lsblk-rs/config.toml`, or under `~/.config` when that
+/// is unset.
+fn config_path() -> Option<PathBuf> {
+	match env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+		_ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+	}.map(|dir| dir.join("lsblk-rs").join("config.toml"))
+}
+
+/// Reads the config file, if there is one, and `LSBLK_COLUMNS`, which
+/// takes precedence over the file's columns as it does for `lsblk`.
+pub fn load() -> Result<Config, String> {
+	let mut config = match config_path() {
+		Some(path) => match fs::read_to_string(&path) {
+			Ok(text) => parse_config(&text).map_err(|err| format!("{}: {}", path.display(), err))?,
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => Config::default(),
+			Err(err) => return Err(format!("{}: {}", path.display(), err)),
+		},
+		None => Config::default(),
+	};
+	if let Ok(columns) = env::var("LSBLK_COLUMNS") {
+		config.columns = Some(column::parse_columns(&columns).map_err(|err| format!("LSBLK_COLUMNS: {}", err))?);
+	}
+	Ok(config)
+}
+
+impl Config {
+	/// Fills in the settings that the command line left alone. Each group
+	/// of related options is taken as a whole, so that `--si` is not undone
+	/// by `units = "bytes"`, nor `-f` by the configured columns.
+	pub fn apply(self, opts : &mut Options) {
+		if opts.columns.is_none() && !opts.fs {
+			opts.columns = self.columns;
+		}
+		if !opts.bytes && !opts.si {
+			match self.units {
+				Some(Units::Bytes) => opts.bytes = true,
+				Some(Units::Si) => opts.si = true,
+				Some(Units::Binary) | None => (),
+			}
+		}
+		if opts.exclude.is_none() && opts.include.is_none() {
+			opts.exclude = self.exclude;
+			opts.include = self.include;
+		}
+		if opts.fstypes.is_none() && opts.exclude_fstypes.is_none() {
+			opts.fstypes = self.fstypes;
+			opts.exclude_fstypes = self.exclude_fstypes;
+		}
+	}
+}
+
+#[test]
+fn test_apply_config() {
+	use options;
+
+	let parse = |args : &[&str], config : &str| {
+		let mut opts = options::parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+		parse_config(config).unwrap().apply(&mut opts);
+		opts
+	};
+	let config = "columns = NAME,SIZE\nunits = bytes\nexclude = 7";
+
+	let opts = parse(&[], "");
+	assert!(opts.columns.is_none());
+	assert!(!opts.bytes && !opts.si);
+	assert!(opts.exclude.is_none());
+
+	let opts = parse(&[], config);
+	assert!(opts.columns == Some(vec![Column::Name, Column::Size]));
+	assert!(opts.bytes);
+	assert!(opts.exclude == Some(vec![7]));
+
+	let opts = parse(&["-o", "NAME,TYPE", "--si", "-I", "8"], config);
+	assert!(opts.columns == Some(vec![Column::Name, Column::Type]));
+	assert!(opts.si && !opts.bytes);
+	assert!(opts.include == Some(vec![8]));
+	assert!(opts.exclude.is_none());
+
+	let opts = parse(&["-f"], config);
+	assert!(opts.columns.is_none());
+}
@@ -0,0 +1,363 @@
+use lsblk::Block;
+use lsblk::BlockMetadata;
+
+/// RAM disks are hidden unless an explicit `--exclude` list is given.
+pub const DEFAULT_EXCLUDE : &[u32] = &[1];
+
+/// Parses a comma-separated list of major numbers such as `7,1`.
+pub fn parse_majors(list : &str) -> Result<Vec<u32>, String> {
+	list.split(',').map(|major| {
+		major.trim().parse::<u32>().map_err(|_| format!("invalid major number '{}'", major))
+	}).collect()
+}
+
+#[test]
+fn test_parse_majors() {
+	assert!(parse_majors("7") == Ok(vec![7]));
+	assert!(parse_majors("7,1") == Ok(vec![7, 1]));
+	assert!(parse_majors("8, 259") == Ok(vec![8, 259]));
+	assert!(parse_majors("7,loop") == Err("invalid major number 'loop'".to_owned()));
+	assert!(parse_majors("").is_err());
+}
+
+/// Removes every disk and partition whose major number is in `majors`.
+/// Excluding a disk also removes all of its partitions.
+pub fn exclude_majors(blocks : &mut Vec<Block>, majors : &[u32]) {
+	blocks.retain(|block| !majors.contains(&block.majmin.major));
+	for block in blocks {
+		block.partitions.retain(|part| !majors.contains(&part.majmin.major));
+	}
+}
+
+/// Keeps only the disks and partitions whose major number is in `majors`.
+pub fn include_majors(blocks : &mut Vec<Block>, majors : &[u32]) {
+	blocks.retain(|block| majors.contains(&block.majmin.major));
+	for block in blocks {
+		block.partitions.retain(|part| majors.contains(&part.majmin.major));
+	}
+}
+
+/// Keeps only the mounted partitions, and the disks that are mounted or
+/// still have a mounted partition.
+pub fn mounted_only(blocks : &mut Vec<Block>) {
+	for block in blocks.iter_mut() {
+		block.partitions.retain(|part| !part.mountpoint.is_empty());
+	}
+	blocks.retain(|block| !block.mountpoint.is_empty() || !block.partitions.is_empty());
+}
+
+/// Keeps only the disks with removable media, such as USB sticks and SD
+/// cards, along with all of their partitions.
+pub fn removable_only(blocks : &mut Vec<Block>) {
+	blocks.retain(|block| block.removable == Some(1));
+}
+
+/// Removes the disks and partitions that report a size of zero, such as
+/// card readers without a card. A size that could not be read is kept.
+pub fn hide_empty(blocks : &mut Vec<Block>) {
+	blocks.retain(|block| block.size != Some(0));
+	for block in blocks {
+		block.partitions.retain(|part| part.size != Some(0));
+	}
+}
+
+/// Parses a comma-separated list of filesystem types such as `ext4,xfs`.
+pub fn parse_fstypes(list : &str) -> Vec<String> {
+	list.split(',').map(|fstype| fstype.trim().to_owned()).filter(|fstype| !fstype.is_empty()).collect()
+}
+
+#[test]
+fn test_parse_fstypes() {
+	assert!(parse_fstypes("ext4") == ["ext4"]);
+	assert!(parse_fstypes("ext4, xfs,") == ["ext4", "xfs"]);
+	assert!(parse_fstypes("").is_empty());
+}
+
+/// Whether udev detected one of `fstypes` on the device. A device with no
+/// detected filesystem never matches.
+fn fstype_matches(metadata : &Option<BlockMetadata>, fstypes : &[String]) -> bool {
+	metadata.as_ref().and_then(|meta| meta.id_fs_type.as_ref()).is_some_and(|fstype| fstypes.contains(fstype))
+}
+
+#[test]
+fn test_fstype_matches() {
+	let fstypes = parse_fstypes("ext4,xfs");
+	let meta = |fstype : Option<&str>| Some(BlockMetadata { id_fs_type: fstype.map(|fstype| fstype.to_owned()), ..BlockMetadata::default() });
+	assert!(fstype_matches(&meta(Some("ext4")), &fstypes));
+	assert!(fstype_matches(&meta(Some("xfs")), &fstypes));
+	assert!(!fstype_matches(&meta(Some("swap")), &fstypes));
+	assert!(!fstype_matches(&meta(None), &fstypes));
+	assert!(!fstype_matches(&None, &fstypes));
+}
+
+/// Keeps only the disks and partitions with one of `fstypes`, along with
+/// the disks that still have a partition left.
+pub fn include_fstypes(blocks : &mut Vec<Block>, fstypes : &[String]) {
+	for block in blocks.iter_mut() {
+		block.partitions.retain(|part| fstype_matches(&part.metadata, fstypes));
+	}
+	blocks.retain(|block| fstype_matches(&block.metadata, fstypes) || !block.partitions.is_empty());
+}
+
+/// The major numbers that only ever belong to devices with no hardware of
+/// their own: RAM disks and loop devices.
+const PSEUDO_MAJORS : &[u32] = &[1, 7];
+
+/// Whether a device is made up by the kernel rather than backed by real
+/// hardware. device-mapper and zram are given whichever major number is
+/// free, which on other systems can be virtio's, so they are told apart by
+/// name instead.
+pub fn is_pseudo(block : &Block) -> bool {
+	PSEUDO_MAJORS.contains(&block.majmin.major) ||
+		block.dm_name.is_some() ||
+		block.name.starts_with("dm-") ||
+		block.name.starts_with("zram")
+}
+
+/// Removes RAM disks, loop devices, device-mapper devices and zram, with
+/// their partitions, leaving the disks backed by hardware such as SCSI
+/// (8), NVMe (259), MMC (179) and virtio.
+pub fn exclude_pseudo(blocks : &mut Vec<Block>) {
+	blocks.retain(|block| !is_pseudo(block));
+}
+
+/// Removes the disks that report a size of zero and have no partitions,
+/// which is how the empty slots of a multi-card reader look. Unlike
+/// `hide_empty` this never touches partitions, or a disk whose size could
+/// not be read.
+pub fn exclude_zero_size(blocks : &mut Vec<Block>) {
+	blocks.retain(|block| block.size != Some(0) || !block.partitions.is_empty());
+}
+
+/// Removes every disk and partition with one of `fstypes`. Devices with no
+/// detected filesystem are kept.
+pub fn exclude_fstypes(blocks : &mut Vec<Block>, fstypes : &[String]) {
+	blocks.retain(|block| !fstype_matches(&block.metadata, fstypes));
+	for block in blocks {
+		block.partitions.retain(|part| !fstype_matches(&part.metadata, fstypes));
+	}
+}
+
+#[cfg(test)]
+fn test_blocks() -> Vec<Block> {
+	use lsblk::MajorMinor;
+	use lsblk::Partition;
+
+	let block = |name : &str, major, partitions| Block {
+		name: name.to_owned(),
+		majmin: MajorMinor { major, minor: 0 },
+		removable: None,
+		hotplug: None,
+		size: None,
+		readonly: None,
+		rotational: None,
+		logical_block_size: None,
+		physical_block_size: None,
+		alignment_offset: None,
+		minimum_io_size: None,
+		optimal_io_size: None,
+		discard_granularity: None,
+		discard_max_bytes: None,
+		dax: None,
+		scheduler: None,
+		transport: None,
+		state: None,
+		partitions,
+		dm_name: None,
+		raid_level: None,
+		backing_file: None,
+		zram: None,
+		slaves: Vec::new(),
+		holders: Vec::new(),
+		node: None,
+		dev_path: None,
+		metadata: None,
+		mountpoint: String::new(),
+		mountpoints: Vec::new(),
+		mount_options: String::new(),
+	};
+	let partition = |name : &str, major| Partition {
+		name: name.to_owned(),
+		majmin: MajorMinor { major, minor: 1 },
+		removable: None,
+		size: None,
+		start: None,
+		alignment_offset: None,
+		readonly: None,
+		holders: Vec::new(),
+		node: None,
+		dev_path: None,
+		metadata: None,
+		mountpoint: String::new(),
+		mountpoints: Vec::new(),
+		mount_options: String::new(),
+	};
+
+	vec![
+		block("sda", 8, vec![partition("sda1", 8), partition("sda2", 8)]),
+		block("loop0", 7, vec![partition("loop0p1", 259)]),
+		block("ram0", 1, vec![]),
+		block("nvme0n1", 259, vec![partition("nvme0n1p1", 259)]),
+	]
+}
+
+#[cfg(test)]
+fn names(blocks : &[Block]) -> Vec<String> {
+	let mut names = Vec::new();
+	for block in blocks {
+		names.push(block.name.to_owned());
+		names.extend(block.partitions.iter().map(|part| part.name.to_owned()));
+	}
+	names
+}
+
+#[test]
+fn test_exclude_majors() {
+	let mut blocks = test_blocks();
+	exclude_majors(&mut blocks, DEFAULT_EXCLUDE);
+	assert!(names(&blocks) == ["sda", "sda1", "sda2", "loop0", "loop0p1", "nvme0n1", "nvme0n1p1"]);
+
+	let mut blocks = test_blocks();
+	exclude_majors(&mut blocks, &[7, 1]);
+	assert!(names(&blocks) == ["sda", "sda1", "sda2", "nvme0n1", "nvme0n1p1"]);
+
+	let mut blocks = test_blocks();
+	exclude_majors(&mut blocks, &[259]);
+	assert!(names(&blocks) == ["sda", "sda1", "sda2", "loop0", "ram0"]);
+
+	let mut blocks = test_blocks();
+	exclude_majors(&mut blocks, &[]);
+	assert!(names(&blocks).len() == 8);
+}
+
+#[test]
+fn test_include_majors() {
+	let mut blocks = test_blocks();
+	include_majors(&mut blocks, &[8, 259]);
+	assert!(names(&blocks) == ["sda", "sda1", "sda2", "nvme0n1", "nvme0n1p1"]);
+
+	let mut blocks = test_blocks();
+	include_majors(&mut blocks, &[7]);
+	assert!(names(&blocks) == ["loop0"]);
+
+	let mut blocks = test_blocks();
+	include_majors(&mut blocks, &[253]);
+	assert!(names(&blocks).is_empty());
+}
+
+#[test]
+fn test_mounted_only() {
+	let mut blocks = test_blocks();
+	blocks[0].partitions[1].mountpoint = "/".to_owned();
+	blocks[2].mountpoint = "[SWAP]".to_owned();
+	mounted_only(&mut blocks);
+	assert!(names(&blocks) == ["sda", "sda2", "ram0"]);
+
+	let mut blocks = test_blocks();
+	mounted_only(&mut blocks);
+	assert!(names(&blocks).is_empty());
+
+	// A filesystem made on a whole disk, as with `mkfs.ext4 /dev/vdb`.
+	let mut blocks = test_blocks();
+	let mut vdb = blocks[0].clone();
+	vdb.name = "vdb".to_owned();
+	vdb.partitions.clear();
+	vdb.mountpoint = "/data".to_owned();
+	let mut vdc = vdb.clone();
+	vdc.name = "vdc".to_owned();
+	vdc.mountpoint = String::new();
+	blocks.push(vdb);
+	blocks.push(vdc);
+	mounted_only(&mut blocks);
+	assert!(names(&blocks) == ["vdb"]);
+}
+
+#[test]
+fn test_removable_only() {
+	let mut blocks = test_blocks();
+	blocks[0].removable = Some(0);
+	blocks[1].removable = Some(1);
+	blocks[3].removable = Some(1);
+	removable_only(&mut blocks);
+	assert!(names(&blocks) == ["loop0", "loop0p1", "nvme0n1", "nvme0n1p1"]);
+
+	let mut blocks = test_blocks();
+	removable_only(&mut blocks);
+	assert!(names(&blocks).is_empty());
+}
+
+#[test]
+fn test_hide_empty() {
+	let mut blocks = test_blocks();
+	blocks[0].size = Some(34359738368);
+	blocks[0].partitions[0].size = Some(0);
+	blocks[0].partitions[1].size = Some(1048576);
+	blocks[1].size = Some(0);
+	hide_empty(&mut blocks);
+	assert!(names(&blocks) == ["sda", "sda2", "ram0", "nvme0n1", "nvme0n1p1"]);
+}
+
+#[test]
+fn test_exclude_pseudo() {
+	let mut blocks = test_blocks();
+	let mut dm = blocks[0].clone();
+	dm.name = "dm-0".to_owned();
+	dm.majmin.major = 253;
+	dm.dm_name = Some("vg-root".to_owned());
+	dm.partitions.clear();
+	let mut zram = dm.clone();
+	zram.name = "zram0".to_owned();
+	zram.majmin.major = 252;
+	zram.dm_name = None;
+	let mut vda = zram.clone();
+	vda.name = "vda".to_owned();
+	vda.majmin.major = 254;
+	let mut mmc = vda.clone();
+	mmc.name = "mmcblk0".to_owned();
+	mmc.majmin.major = 179;
+	blocks.extend(vec![dm, zram, vda, mmc]);
+	exclude_pseudo(&mut blocks);
+	assert!(names(&blocks) == ["sda", "sda1", "sda2", "nvme0n1", "nvme0n1p1", "vda", "mmcblk0"]);
+}
+
+#[test]
+fn test_exclude_zero_size() {
+	let mut blocks = test_blocks();
+	blocks[0].size = Some(0);
+	blocks[0].partitions[0].size = Some(0);
+	blocks[2].size = Some(0);
+	blocks[3].size = Some(512110190592);
+	exclude_zero_size(&mut blocks);
+	assert!(names(&blocks) == ["sda", "sda1", "sda2", "loop0", "loop0p1", "nvme0n1", "nvme0n1p1"]);
+
+	let mut blocks = test_blocks();
+	blocks[2].size = None;
+	exclude_zero_size(&mut blocks);
+	assert!(names(&blocks).contains(&"ram0".to_owned()));
+}
+
+#[cfg(test)]
+fn set_fstype(metadata : &mut Option<BlockMetadata>, fstype : &str) {
+	*metadata = Some(BlockMetadata { id_fs_type: Some(fstype.to_owned()), ..BlockMetadata::default() });
+}
+
+#[test]
+fn test_include_fstypes() {
+	let mut blocks = test_blocks();
+	set_fstype(&mut blocks[0].partitions[0].metadata, "vfat");
+	set_fstype(&mut blocks[0].partitions[1].metadata, "ext4");
+	set_fstype(&mut blocks[1].metadata, "squashfs");
+	set_fstype(&mut blocks[3].partitions[0].metadata, "swap");
+	include_fstypes(&mut blocks, &parse_fstypes("ext4,squashfs"));
+	assert!(names(&blocks) == ["sda", "sda2", "loop0"]);
+}
+
+#[test]
+fn test_exclude_fstypes() {
+	let mut blocks = test_blocks();
+	set_fstype(&mut blocks[0].partitions[0].metadata, "vfat");
+	set_fstype(&mut blocks[0].partitions[1].metadata, "swap");
+	set_fstype(&mut blocks[2].metadata, "swap");
+	exclude_fstypes(&mut blocks, &parse_fstypes("swap"));
+	assert!(names(&blocks) == ["sda", "sda1", "loop0", "loop0p1", "nvme0n1", "nvme0n1p1"]);
+}
@@ -0,0 +1,152 @@
+use std::io;
+use std::io::Write;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Json {
+	Null,
+	Bool(bool),
+	Number(u64),
+	/// A number that can be negative.
+	Integer(i64),
+	String(String),
+	Array(Vec<Json>),
+	Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+	pub fn flag(flag : Option<u64>) -> Json {
+		flag.map_or(Json::Null, |flag| Json::Bool(flag != 0))
+	}
+
+	pub fn number(n : Option<u64>) -> Json {
+		n.map_or(Json::Null, Json::Number)
+	}
+
+	pub fn integer(n : Option<i64>) -> Json {
+		n.map_or(Json::Null, Json::Integer)
+	}
+
+	pub fn text(s : &str) -> Json {
+		if s.is_empty() {
+			Json::Null
+		} else {
+			Json::String(s.to_owned())
+		}
+	}
+
+	pub fn write_pretty(&self, out : &mut dyn Write) -> io::Result<()> {
+		self.write_indented(out, 0)?;
+		writeln!(out)
+	}
+
+	fn write_indented(&self, out : &mut dyn Write, level : usize) -> io::Result<()> {
+		let indent = |level : usize| "   ".repeat(level);
+
+		match *self {
+			Json::Array(ref items) if !items.is_empty() => {
+				writeln!(out, "[")?;
+				for (i, item) in items.iter().enumerate() {
+					write!(out, "{}", indent(level + 1))?;
+					item.write_indented(out, level + 1)?;
+					writeln!(out, "{}", if i+1 < items.len() { "," } else { "" })?;
+				}
+				write!(out, "{}]", indent(level))
+			},
+			Json::Object(ref fields) if !fields.is_empty() => {
+				writeln!(out, "{{")?;
+				for (i, &(key, ref value)) in fields.iter().enumerate() {
+					write!(out, "{}{}: ", indent(level + 1), escape(key))?;
+					value.write_indented(out, level + 1)?;
+					writeln!(out, "{}", if i+1 < fields.len() { "," } else { "" })?;
+				}
+				write!(out, "{}}}", indent(level))
+			},
+			_ => self.write_compact(out),
+		}
+	}
+
+	pub fn write_compact(&self, out : &mut dyn Write) -> io::Result<()> {
+		match *self {
+			Json::Null => write!(out, "null"),
+			Json::Bool(b) => write!(out, "{}", b),
+			Json::Number(n) => write!(out, "{}", n),
+			Json::Integer(n) => write!(out, "{}", n),
+			Json::String(ref s) => write!(out, "{}", escape(s)),
+			Json::Array(ref items) => {
+				write!(out, "[")?;
+				for (i, item) in items.iter().enumerate() {
+					if i > 0 {
+						write!(out, ",")?;
+					}
+					item.write_compact(out)?;
+				}
+				write!(out, "]")
+			},
+			Json::Object(ref fields) => {
+				write!(out, "{{")?;
+				for (i, &(key, ref value)) in fields.iter().enumerate() {
+					if i > 0 {
+						write!(out, ",")?;
+					}
+					write!(out, "{}:", escape(key))?;
+					value.write_compact(out)?;
+				}
+				write!(out, "}}")
+			},
+		}
+	}
+}
+
+fn escape(s : &str) -> String {
+	let mut escaped = String::with_capacity(s.len() + 2);
+	escaped.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => escaped.push_str("\\\""),
+			'\\' => escaped.push_str("\\\\"),
+			'\n' => escaped.push_str("\\n"),
+			'\r' => escaped.push_str("\\r"),
+			'\t' => escaped.push_str("\\t"),
+			c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+			c => escaped.push(c),
+		}
+	}
+	escaped.push('"');
+	escaped
+}
+
+#[test]
+fn test_escape() {
+	assert!(escape("sda") == "\"sda\"");
+	assert!(escape("/mnt/my disk") == "\"/mnt/my disk\"");
+	assert!(escape("a\"b\\c") == "\"a\\\"b\\\\c\"");
+	assert!(escape("a\nb\u{1}") == "\"a\\nb\\u0001\"");
+}
+
+#[cfg(test)]
+fn to_string(json : &Json, pretty : bool) -> String {
+	let mut out = Vec::new();
+	if pretty {
+		json.write_pretty(&mut out).unwrap();
+	} else {
+		json.write_compact(&mut out).unwrap();
+	}
+	String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_write() {
+	let json = Json::Object(vec![
+		("name", Json::text("sda")),
+		("size", Json::number(Some(512))),
+		("rm", Json::flag(Some(0))),
+		("mountpoint", Json::text("")),
+		("children", Json::Array(vec![])),
+	]);
+
+	assert!(to_string(&json, false) ==
+		r#"{"name":"sda","size":512,"rm":false,"mountpoint":null,"children":[]}"#);
+	assert!(to_string(&json, true) ==
+		"{\n   \"name\": \"sda\",\n   \"size\": 512,\n   \"rm\": false,\n   \"mountpoint\": null,\n   \"children\": []\n}\n");
+}
@@ -0,0 +1,1349 @@
+#[macro_use]
+extern crate lazy_static;
+extern crate libc;
+extern crate regex;
+#[cfg(feature = "serde")]
+extern crate serde;
+
+use std::error;
+use std::fmt;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::fs::DirEntry;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::mem;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use regex::Regex;
+use std::str::FromStr;
+
+mod probe;
+
+lazy_static! {
+	static ref MAJOR_MINOR_RE : Regex = Regex::new(r"^([0-9]+):([0-9]+)$").unwrap();
+	static ref PROC_SWAPS_LINE_RE : Regex = Regex::new(r"^(/[^ ]+) +.+$").unwrap();
+	static ref UEVENT_LINE_RE : Regex = Regex::new(r"^E:([^=]+)=(.+)$").unwrap();
+}
+
+macro_rules! none {
+	($x:expr) => (match $x {
+		Ok(y) => y,
+		_ => return None
+	})
+}
+
+/// The ways that scanning for block devices can fail.
+#[derive(Debug)]
+pub enum LsblkError {
+	/// A device number that is not of the form `major:minor`.
+	ParseMajorMinor(String),
+	/// A file or directory under sysfs or procfs that could not be read.
+	Sysfs { path : PathBuf, source : io::Error },
+	/// A path given as a device that is not a block device the kernel knows.
+	UnknownDevice(PathBuf),
+	Io(io::Error),
+}
+
+impl fmt::Display for LsblkError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			LsblkError::ParseMajorMinor(ref s) => write!(f, "invalid device number '{}'", s),
+			LsblkError::Sysfs { ref path, ref source } => write!(f, "{}: {}", path.display(), source),
+			LsblkError::UnknownDevice(ref path) => write!(f, "{}: not a block device", path.display()),
+			LsblkError::Io(ref err) => write!(f, "{}", err),
+		}
+	}
+}
+
+impl error::Error for LsblkError {
+	fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+		match *self {
+			LsblkError::ParseMajorMinor(_) | LsblkError::UnknownDevice(_) => None,
+			LsblkError::Sysfs { ref source, .. } => Some(source),
+			LsblkError::Io(ref err) => Some(err),
+		}
+	}
+}
+
+impl From<io::Error> for LsblkError {
+	fn from(err : io::Error) -> LsblkError {
+		LsblkError::Io(err)
+	}
+}
+
+/// The locations of the kernel and udev files that are scanned. The default
+/// is the running system; tests and chroots can point these elsewhere.
+///
+/// A context remembers the udev properties it has read, so a scan that
+/// should pick up changes made by udev needs a fresh one. Clones share what
+/// has been read, so every scan through a context, whether by
+/// `list_block_devices_in`, `iter_block_devices_in` or
+/// `list_block_devices_by_number_in`, adds to the same cache.
+#[derive(Debug)]
+pub struct Context {
+	pub sys_block : PathBuf,
+	/// Links from each block device's number to its directory in sysfs.
+	pub sys_dev_block : PathBuf,
+	pub proc_mountinfo : PathBuf,
+	pub proc_mounts : PathBuf,
+	pub proc_swaps : PathBuf,
+	pub udev_data : PathBuf,
+	/// Where the device nodes are, for `probe`.
+	pub dev : PathBuf,
+	/// Read the superblock of a device that udev has no properties for,
+	/// to find its filesystem type and UUID, and ask a device that sysfs
+	/// says is empty for its size. This opens the device node itself, which
+	/// usually needs root.
+	pub probe : bool,
+	/// Finds the size in bytes of an open device node, for `probe`.
+	pub device_size : fn(&File) -> io::Result<u64>,
+	/// Finds the number of the block device node at a path, for
+	/// `resolve_device`.
+	pub device_number : fn(&Path) -> Option<MajorMinor>,
+	/// Reads a device's properties file from `udev_data`.
+	pub read_udev_data : fn(&Path) -> io::Result<String>,
+	/// The udev properties already read, so that a device looked up again
+	/// is not read twice.
+	udev_cache : Arc<Mutex<HashMap<MajorMinor, Option<BlockMetadata>>>>,
+}
+
+impl Context {
+	/// A context with every path relocated underneath `root`.
+	pub fn with_root<P: AsRef<Path>>(root : P) -> Context {
+		let root = root.as_ref();
+		Context {
+			sys_block: root.join("sys/block"),
+			sys_dev_block: root.join("sys/dev/block"),
+			proc_mountinfo: root.join("proc/self/mountinfo"),
+			proc_mounts: root.join("proc/mounts"),
+			proc_swaps: root.join("proc/swaps"),
+			udev_data: root.join("run/udev/data"),
+			dev: root.join("dev"),
+			probe: false,
+			device_size: blkgetsize64,
+			device_number: block_device_number,
+			read_udev_data: read_udev_file,
+			udev_cache: Arc::new(Mutex::new(HashMap::new())),
+		}
+	}
+
+	/// The udev properties of `device`, calling `load` to read them only the
+	/// first time the device is asked for.
+	fn udev_metadata<F>(&self, device : &MajorMinor, load : F) -> Option<BlockMetadata>
+		where F: FnOnce() -> Option<BlockMetadata> {
+		let mut cache = self.udev_cache.lock().unwrap();
+		cache.entry(*device).or_insert_with(load).clone()
+	}
+}
+
+impl Clone for Context {
+	fn clone(&self) -> Context {
+		Context {
+			sys_block: self.sys_block.clone(),
+			sys_dev_block: self.sys_dev_block.clone(),
+			proc_mountinfo: self.proc_mountinfo.clone(),
+			proc_mounts: self.proc_mounts.clone(),
+			proc_swaps: self.proc_swaps.clone(),
+			udev_data: self.udev_data.clone(),
+			dev: self.dev.clone(),
+			probe: self.probe,
+			device_size: self.device_size,
+			device_number: self.device_number,
+			read_udev_data: self.read_udev_data,
+			udev_cache: Arc::clone(&self.udev_cache),
+		}
+	}
+}
+
+#[test]
+fn test_context_udev_metadata() {
+	use std::cell::Cell;
+
+	let ctx = Context::with_root("/nonexistent");
+	let reads = Cell::new(0);
+	let load = || {
+		reads.set(reads.get() + 1);
+		Some(BlockMetadata { id_type: "disk".to_owned(), ..BlockMetadata::default() })
+	};
+	let sda = MajorMinor { major: 8, minor: 0 };
+	let sdb = MajorMinor { major: 8, minor: 16 };
+
+	assert!(ctx.udev_metadata(&sda, load).is_some_and(|meta| meta.id_type == "disk"));
+	assert!(ctx.udev_metadata(&sda, load).is_some_and(|meta| meta.id_type == "disk"));
+	assert!(reads.get() == 1);
+	assert!(ctx.clone().udev_metadata(&sda, load).is_some());
+	assert!(reads.get() == 1);
+	// A clone shares the cache, in both directions.
+	ctx.clone().udev_metadata(&sdb, load);
+	assert!(reads.get() == 2);
+	ctx.udev_metadata(&sdb, load);
+	assert!(reads.get() == 2);
+}
+
+impl Default for Context {
+	fn default() -> Context {
+		Context::with_root("/")
+	}
+}
+
+/// A device number, as found in the `dev` attribute of a sysfs block device.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq, Eq, Hash)]
+pub struct MajorMinor {
+	pub major : u32,
+	pub minor : u32,
+}
+
+impl MajorMinor {
+	fn udev_path(&self, udev_data : &Path) -> PathBuf {
+		let filename = format!("b{}", self);
+		udev_data.join(filename)
+	}
+}
+
+impl fmt::Display for MajorMinor {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}", self.major, self.minor)
+	}
+}
+
+impl FromStr for MajorMinor {
+	type Err = LsblkError;
+	fn from_str(s: &str) -> Result<MajorMinor, LsblkError> {
+		MAJOR_MINOR_RE.captures(s).and_then(|caps| {
+			match (caps.at(1).unwrap().parse::<u32>(), caps.at(2).unwrap().parse::<u32>()) {
+				(Ok(major), Ok(minor)) => Some(MajorMinor { major, minor }),
+				_ => None,
+			}
+		}).ok_or_else(|| LsblkError::ParseMajorMinor(s.to_owned()))
+	}
+}
+
+/// Device numbers are written as `maj:min`, as they appear in sysfs, rather
+/// than as a pair of fields.
+#[cfg(feature = "serde")]
+impl serde::Serialize for MajorMinor {
+	fn serialize<S: serde::Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for MajorMinor {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer : D) -> Result<MajorMinor, D::Error> {
+		let s = String::deserialize(deserializer)?;
+		MajorMinor::from_str(&s).map_err(serde::de::Error::custom)
+	}
+}
+
+#[test]
+fn test_major_minor_from_str() {
+	assert!(MajorMinor::from_str("8:0").ok() == Some(MajorMinor { major: 8, minor: 0 }));
+	assert!(MajorMinor::from_str("259:3").ok() == Some(MajorMinor { major: 259, minor: 3 }));
+	assert!(MajorMinor::from_str("253:1048575").ok() == Some(MajorMinor { major: 253, minor: 1048575 }));
+	assert!(MajorMinor::from_str("259").is_err());
+	assert!(MajorMinor::from_str("99999999999:0").is_err());
+	match MajorMinor::from_str("sda") {
+		Err(LsblkError::ParseMajorMinor(ref s)) => assert!(s == "sda"),
+		_ => panic!("expected a ParseMajorMinor error"),
+	}
+	assert!(MajorMinor::from_str("8:x").unwrap_err().to_string() == "invalid device number '8:x'");
+}
+
+/// Properties recorded by udev in `/run/udev/data`.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Default)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BlockMetadata {
+	pub id_type : String,
+	pub id_fs_type : Option<String>,
+	pub id_fs_uuid : Option<String>,
+	pub id_fs_label : Option<String>,
+	pub id_part_entry_name : Option<String>,
+	/// The partition's own UUID, as used by `PARTUUID=`. Unlike `id_fs_uuid`
+	/// it survives reformatting.
+	pub id_part_entry_uuid : Option<String>,
+	/// The partition type, a GUID on GPT disks and a hex byte such as `0x83`
+	/// on MBR disks.
+	pub id_part_entry_type : Option<String>,
+	pub id_model : Option<String>,
+	pub id_serial_short : Option<String>,
+	/// The vendor-prefixed serial, for devices without `ID_SERIAL_SHORT`.
+	pub id_serial : Option<String>,
+	pub id_vendor : Option<String>,
+	pub id_bus : Option<String>,
+	/// The World Wide Name, which every path to a multipath device shares.
+	pub id_wwn : Option<String>,
+	/// `id_wwn` with the vendor extension that some arrays need to tell
+	/// their volumes apart.
+	pub id_wwn_with_extension : Option<String>,
+}
+
+/// The owner and permissions of a device node.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceNode {
+	pub uid : u32,
+	pub gid : u32,
+	/// The file type and permission bits, as in `st_mode`.
+	pub mode : u32,
+}
+
+fn read_device_node(ctx : &Context, name : &str) -> Option<DeviceNode> {
+	let meta = fs::metadata(ctx.dev.join(name)).ok()?;
+	Some(DeviceNode { uid: meta.uid(), gid: meta.gid(), mode: meta.mode() })
+}
+
+/// The path of a device's node. A device-mapper device goes by its
+/// `/dev/mapper` link, as in `lsblk`, or by `/dev/dm-N` without one.
+fn read_dev_path(ctx : &Context, name : &str, dm_name : Option<&str>) -> Option<PathBuf> {
+	let mapper = dm_name.map(|dm_name| format!("mapper/{}", dm_name));
+	mapper.as_deref().into_iter().chain(Some(name))
+		.find(|node| ctx.dev.join(node).exists())
+		.map(|node| Path::new("/dev").join(node))
+}
+
+/// A partition of a `Block` device. Sizes are in bytes.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Partition {
+	pub name : String,
+	pub majmin : MajorMinor,
+	pub removable : Option<u64>,
+	pub size : Option<u64>,
+	/// The first sector of the partition on its disk, from `start`. This is
+	/// always counted in 512-byte sectors.
+	pub start : Option<u64>,
+	/// Like `Block::alignment_offset`, for the partition's own start.
+	pub alignment_offset : Option<i64>,
+	pub readonly : Option<u64>,
+	/// The kernel names of the devices built on this one, from `holders/`.
+	pub holders : Vec<String>,
+	/// The owner and permissions of `/dev/<name>`, which some devices lack.
+	pub node : Option<DeviceNode>,
+	/// The device's node, such as `/dev/sda1`, if it exists.
+	pub dev_path : Option<PathBuf>,
+
+	pub metadata : Option<BlockMetadata>,
+	pub mountpoint : String,
+	pub mountpoints : Vec<String>,
+	pub mount_options : String,
+}
+
+/// A whole block device from `/sys/block`. Sizes are in bytes.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Block {
+	pub name : String,
+	pub majmin : MajorMinor,
+	pub removable : Option<u64>,
+	/// Whether the device sits on a hotpluggable bus such as USB. Unlike
+	/// `removable`, this describes the connection rather than the media.
+	pub hotplug : Option<u64>,
+	pub size : Option<u64>,
+	pub readonly : Option<u64>,
+	pub rotational : Option<u64>,
+	/// The smallest unit the device can address, from
+	/// `queue/logical_block_size`. This is often 512 even on disks with 4K
+	/// physical sectors.
+	pub logical_block_size : Option<u64>,
+	/// The smallest unit the device can write without a read-modify-write,
+	/// from `queue/physical_block_size`.
+	pub physical_block_size : Option<u64>,
+	/// How many bytes the device's start is offset from its physical
+	/// sectors, from `alignment_offset`. Anything but zero slows down
+	/// writes, and -1 means the device cannot be aligned at all.
+	pub alignment_offset : Option<i64>,
+	/// The preferred minimum I/O size in bytes, from `queue/minimum_io_size`.
+	pub minimum_io_size : Option<u64>,
+	/// The preferred I/O size in bytes, such as a RAID stripe width. Zero
+	/// means the device does not say.
+	pub optimal_io_size : Option<u64>,
+	/// The discard (TRIM) granularity in bytes. Zero means the device does
+	/// not support discard.
+	pub discard_granularity : Option<u64>,
+	/// The most bytes that one discard request can cover.
+	pub discard_max_bytes : Option<u64>,
+	/// Whether the device supports direct access (DAX), which lets a
+	/// filesystem on persistent memory bypass the page cache. From
+	/// `queue/dax`, which older kernels lack.
+	pub dax : Option<u64>,
+	/// The active I/O scheduler, such as `mq-deadline`.
+	pub scheduler : Option<String>,
+	pub transport : Option<String>,
+	/// The SCSI device state from `device/state`, such as `running` or
+	/// `offline`. NVMe and virtual devices have none.
+	pub state : Option<String>,
+	pub partitions : Vec<Partition>,
+	/// The device-mapper name from `dm/name`, e.g. `vg-root` for `dm-0`.
+	pub dm_name : Option<String>,
+	/// The RAID level of an md array from `md/level`, such as `raid1`.
+	pub raid_level : Option<String>,
+	/// The file behind a loop device, from `loop/backing_file`. This is
+	/// `None` for other devices and for loop devices that are not attached.
+	pub backing_file : Option<String>,
+	/// How much a zram device holds, from `mm_stat`. `None` for other
+	/// devices.
+	pub zram : Option<ZramStat>,
+	/// The kernel names of the devices this one is built on, from `slaves/`.
+	pub slaves : Vec<String>,
+	/// The kernel names of the devices built on this one, from `holders/`.
+	pub holders : Vec<String>,
+	/// The owner and permissions of `/dev/<name>`, which some devices lack.
+	pub node : Option<DeviceNode>,
+	/// The device's node, such as `/dev/sda1`, if it exists.
+	pub dev_path : Option<PathBuf>,
+
+	pub metadata : Option<BlockMetadata>,
+	pub mountpoint : String,
+	pub mountpoints : Vec<String>,
+	pub mount_options : String,
+}
+
+fn parse_block_file<T: FromStr>(path : &Path, filename : &str) -> Option<T> {
+	let filepath = PathBuf::from(path).join(filename);
+	let mut file = none!(File::open(filepath));
+
+	let contents = &mut String::new();
+	let _ = none!(file.read_to_string(contents));
+	T::from_str(contents.trim()).ok()
+}
+
+#[test]
+fn test_parse_block_file_read_error() {
+	// Opening a directory succeeds, but reading from it fails with EISDIR.
+	let dir = std::env::temp_dir();
+	assert!(parse_block_file::<u64>(&dir, ".").is_none());
+	assert!(parse_block_file::<String>(&dir, ".").is_none());
+	assert!(parse_block_file::<u64>(&dir, "lsblk-nonexistent-attribute").is_none());
+}
+
+/// `_IOR(0x12, 114, size_t)` from `<linux/fs.h>`.
+const BLKGETSIZE64 : libc::Ioctl = (2 << 30 | mem::size_of::<usize>() << 16 | 0x12 << 8 | 114) as libc::Ioctl;
+
+/// Asks the kernel for the size in bytes of the device open as `dev`.
+fn blkgetsize64(dev : &File) -> io::Result<u64> {
+	let mut size : u64 = 0;
+	match unsafe { libc::ioctl(dev.as_raw_fd(), BLKGETSIZE64, &mut size) } {
+		0 => Ok(size),
+		_ => Err(io::Error::last_os_error()),
+	}
+}
+
+/// The device number of the block device node at `path`.
+fn block_device_number(path : &Path) -> Option<MajorMinor> {
+	let meta = fs::metadata(path).ok()?;
+	if !meta.file_type().is_block_device() {
+		return None;
+	}
+	Some(MajorMinor { major: libc::major(meta.rdev()), minor: libc::minor(meta.rdev()) })
+}
+
+/// Finds the kernel name, such as `sda1`, of the block device node at
+/// `path`, by looking its number up in `/sys/dev/block`.
+pub fn resolve_device<P: AsRef<Path>>(ctx : &Context, path : P) -> Result<String, LsblkError> {
+	let path = path.as_ref();
+	let unknown = || LsblkError::UnknownDevice(path.to_owned());
+	let majmin = (ctx.device_number)(path).ok_or_else(unknown)?;
+	let sysfs = ctx.sys_dev_block.join(majmin.to_string()).canonicalize().map_err(|_| unknown())?;
+	sysfs.file_name().map(|name| name.to_string_lossy().into_owned()).ok_or_else(unknown)
+}
+
+/// Converts a count of `sector_size`-byte sectors to bytes.
+pub fn bytes_from_sectors(sectors : u64, sector_size : u64) -> u64 {
+	sectors * sector_size
+}
+
+#[test]
+fn test_bytes_from_sectors() {
+	assert!(bytes_from_sectors(0, 512) == 0);
+	assert!(bytes_from_sectors(2048, 512) == 1048576);
+	assert!(bytes_from_sectors(256, 4096) == 1048576);
+}
+
+/// Reads a sector count such as `size`. sysfs always counts these in
+/// 512-byte sectors, whatever the device's logical block size.
+fn parse_sector_file(path : &Path, filename : &str) -> Option<u64> {
+	parse_block_file::<u64>(path, filename).map(|x| bytes_from_sectors(x, 512))
+}
+
+/// Reads a device's `size`. Some virtual devices report a size of zero in
+/// sysfs even though they hold data, so with `ctx.probe` these are asked
+/// for their size directly.
+fn read_size(ctx : &Context, path : &Path, name : &str) -> Option<u64> {
+	match parse_sector_file(path, "size") {
+		Some(0) if ctx.probe => File::open(ctx.dev.join(name))
+			.and_then(|dev| (ctx.device_size)(&dev))
+			.ok()
+			.or(Some(0)),
+		size => size,
+	}
+}
+
+/// One mount of a filesystem. Mounts read from /proc/mounts rather than
+/// /proc/self/mountinfo always cover the whole filesystem and have their
+/// superblock options folded into `mount_options`.
+#[derive(Debug)]
+#[derive(PartialEq)]
+struct MountInfo {
+	root : String,
+	mountpoint : String,
+	mount_options : String,
+	super_options : String,
+}
+
+impl MountInfo {
+	/// The options in the form /proc/mounts prints them: the per-mount
+	/// options followed by the superblock's own options.
+	fn options(&self) -> String {
+		let mut options : Vec<&str> = self.mount_options.split(',').filter(|option| !option.is_empty()).collect();
+		for option in self.super_options.split(',') {
+			if !option.is_empty() && option != "rw" && option != "ro" && !options.contains(&option) {
+				options.push(option);
+			}
+		}
+		options.join(",")
+	}
+}
+
+#[test]
+fn test_mount_info_options() {
+	let info = MountInfo {
+		root: "/".to_owned(),
+		mountpoint: "/".to_owned(),
+		mount_options: "ro,relatime".to_owned(),
+		super_options: "rw,errors=remount-ro".to_owned(),
+	};
+	assert!(info.options() == "ro,relatime,errors=remount-ro");
+}
+
+/// Decodes the `\NNN` octal escapes the kernel uses for spaces, tabs,
+/// newlines and backslashes in the fields of /proc/mounts.
+fn unescape_mount_field(field : &str) -> String {
+	let bytes = field.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let escape = bytes.get(i + 1..i + 4)
+			.filter(|digits| bytes[i] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+			.map(|digits| digits.iter().fold(0u32, |acc, d| acc * 8 + (d - b'0') as u32));
+		match escape {
+			Some(value) if value <= 0xff => {
+				decoded.push(value as u8);
+				i += 4;
+			},
+			_ => {
+				decoded.push(bytes[i]);
+				i += 1;
+			},
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[test]
+fn test_unescape_mount_field() {
+	assert!(unescape_mount_field("/mnt/data") == "/mnt/data");
+	assert!(unescape_mount_field("/mnt/my\\040disk") == "/mnt/my disk");
+	assert!(unescape_mount_field("a\\011b\\012c\\134d") == "a\tb\nc\\d");
+	assert!(unescape_mount_field("trailing\\04") == "trailing\\04");
+	assert!(unescape_mount_field("not\\999octal") == "not\\999octal");
+}
+
+/// Parses a line of /proc/mounts, which is laid out as
+/// `source mountpoint fstype options dump pass`. Spaces within a field are
+/// escaped, so the fields are split on single spaces. The options may be
+/// missing, but a line without a filesystem type is not a mount.
+fn parse_proc_mounts_line(line : &str) -> Option<(String, MountInfo)> {
+	let mut fields = line.split(' ');
+	let source = fields.next().filter(|field| !field.is_empty())?;
+	let mountpoint = fields.next().filter(|field| !field.is_empty())?;
+	let _fstype = fields.next().filter(|field| !field.is_empty())?;
+	let mount_options = fields.next().unwrap_or("");
+	Some((unescape_mount_field(source), MountInfo {
+		root: "/".to_owned(),
+		mountpoint: unescape_mount_field(mountpoint),
+		mount_options: mount_options.to_owned(),
+		super_options: String::new(),
+	}))
+}
+
+#[test]
+fn test_parse_proc_mounts_line() {
+	assert!(parse_proc_mounts_line("/dev/sda1 /boot vfat rw,relatime,fmask=0022 0 0") ==
+		Some(("/dev/sda1".to_owned(), MountInfo {
+			root: "/".to_owned(),
+			mountpoint: "/boot".to_owned(),
+			mount_options: "rw,relatime,fmask=0022".to_owned(),
+			super_options: "".to_owned(),
+		})));
+	assert!(parse_proc_mounts_line("/dev/sda2 / ext4") ==
+		Some(("/dev/sda2".to_owned(), MountInfo {
+			root: "/".to_owned(),
+			mountpoint: "/".to_owned(),
+			mount_options: "".to_owned(),
+			super_options: "".to_owned(),
+		})));
+	assert!(parse_proc_mounts_line("/dev/sda2 /").is_none());
+	assert!(parse_proc_mounts_line("/dev/sda2 / ").is_none());
+	assert!(parse_proc_mounts_line("").is_none());
+	assert!(parse_proc_mounts_line("server:/export /mnt nfs rw 0 0") ==
+		Some(("server:/export".to_owned(), MountInfo {
+			root: "/".to_owned(),
+			mountpoint: "/mnt".to_owned(),
+			mount_options: "rw".to_owned(),
+			super_options: "".to_owned(),
+		})));
+	assert!(parse_proc_mounts_line("[fd00::1]:/export /mnt/nfs nfs4 rw,vers=4.2,addr=fd00::1 0 0") ==
+		Some(("[fd00::1]:/export".to_owned(), MountInfo {
+			root: "/".to_owned(),
+			mountpoint: "/mnt/nfs".to_owned(),
+			mount_options: "rw,vers=4.2,addr=fd00::1".to_owned(),
+			super_options: "".to_owned(),
+		})));
+	assert!(parse_proc_mounts_line("/dev/disk\\040one /mnt/my\\040disk ext4 rw 0 0") ==
+		Some(("/dev/disk one".to_owned(), MountInfo {
+			root: "/".to_owned(),
+			mountpoint: "/mnt/my disk".to_owned(),
+			mount_options: "rw".to_owned(),
+			super_options: "".to_owned(),
+		})));
+}
+
+/// Parses a line of /proc/self/mountinfo, which is laid out as
+/// `id parent maj:min root mountpoint options [optional...] - fstype source super-options`.
+fn parse_mountinfo_line(line : &str) -> Option<(String, MountInfo)> {
+	let mut fields = line.split(' ');
+	let root = fields.nth(3)?;
+	let mountpoint = fields.next()?;
+	let mount_options = fields.next()?;
+	let mut fields = fields.skip_while(|field| *field != "-").skip(1);
+	let _fstype = fields.next()?;
+	let source = fields.next()?;
+	let super_options = fields.next().unwrap_or("");
+	Some((unescape_mount_field(source), MountInfo {
+		root: unescape_mount_field(root),
+		mountpoint: unescape_mount_field(mountpoint),
+		mount_options: mount_options.to_owned(),
+		super_options: super_options.to_owned(),
+	}))
+}
+
+#[test]
+fn test_parse_mountinfo_line() {
+	assert!(parse_mountinfo_line("29 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw,errors=remount-ro") ==
+		Some(("/dev/sda2".to_owned(), MountInfo {
+			root: "/".to_owned(),
+			mountpoint: "/".to_owned(),
+			mount_options: "rw,relatime".to_owned(),
+			super_options: "rw,errors=remount-ro".to_owned(),
+		})));
+	assert!(parse_mountinfo_line("40 29 8:3 /srv/www /var/www\\040data ro - ext4 /dev/sda3 rw") ==
+		Some(("/dev/sda3".to_owned(), MountInfo {
+			root: "/srv/www".to_owned(),
+			mountpoint: "/var/www data".to_owned(),
+			mount_options: "ro".to_owned(),
+			super_options: "rw".to_owned(),
+		})));
+	assert!(parse_mountinfo_line("29 1 8:2 / / rw,relatime shared:1").is_none());
+	assert!(parse_mountinfo_line("").is_none());
+}
+
+fn sysfs_error(path : &Path, err : io::Error) -> LsblkError {
+	LsblkError::Sysfs { path: path.to_owned(), source: err }
+}
+
+fn read_file(path : &Path) -> Result<String, LsblkError> {
+	let mut file = File::open(path).map_err(|err| sysfs_error(path, err))?;
+	let mut contents = String::new();
+	file.read_to_string(&mut contents).map_err(|err| sysfs_error(path, err))?;
+	Ok(contents)
+}
+
+/// Where a path under `/dev` leads once symlinks such as
+/// `/dev/mapper/<name>` or `/dev/disk/by-uuid/<uuid>` are followed. The
+/// path is looked up in `dev`, so that it moves with the context. Anything
+/// else, such as the `tmpfs` source of a mount, or a path that cannot be
+/// resolved, is returned unchanged.
+fn resolve_dev_path(dev : &Path, path : &str) -> PathBuf {
+	match path.strip_prefix("/dev/") {
+		Some(name) => {
+			let path = dev.join(name);
+			path.canonicalize().unwrap_or(path)
+		},
+		None => PathBuf::from(path),
+	}
+}
+
+/// Reads a mount table, grouping the mounts by their source device. A device
+/// can be mounted by any of its names, such as a `/dev/disk/by-uuid` link,
+/// so the sources are resolved to the device nodes they lead to.
+fn parse_mount_table(ctx : &Context, path : &Path, parse_line : fn(&str) -> Option<(String, MountInfo)>) -> Result<HashMap<PathBuf, Vec<MountInfo>>, LsblkError> {
+	let contents = read_file(path)?;
+
+	let mut mounts = HashMap::new();
+	for (device, entry) in contents.lines().filter_map(parse_line) {
+		mounts.entry(resolve_dev_path(&ctx.dev, &device)).or_insert_with(Vec::new).push(entry);
+	}
+
+	Ok(mounts)
+}
+
+fn parse_proc_swaps_line(line : &str) -> Option<String> {
+	PROC_SWAPS_LINE_RE.captures(line).map(|caps| {
+		unescape_mount_field(caps.at(1).unwrap())
+	})
+}
+
+fn parse_proc_swaps(path : &Path) -> Result<HashSet<String>, LsblkError> {
+	let contents = read_file(path)?;
+
+	let swaps =
+		contents.lines().filter_map(parse_proc_swaps_line).collect();
+
+	Ok(swaps)
+}
+
+#[derive(Debug)]
+struct Mounts {
+	/// The mounts of each device, by its path with symlinks resolved.
+	mounts : HashMap<PathBuf, Vec<MountInfo>>,
+	/// The swap devices, with symlinks resolved.
+	swaps : HashSet<PathBuf>,
+	/// Where the device nodes are, for resolving device names.
+	dev : PathBuf,
+}
+
+impl Mounts {
+	/// Prefers /proc/self/mountinfo, falling back to /proc/mounts on
+	/// systems or chroots where it cannot be read.
+	fn load(ctx : &Context) -> Result<Mounts, LsblkError> {
+		let mounts = match parse_mount_table(ctx, &ctx.proc_mountinfo, parse_mountinfo_line) {
+			Ok(mounts) => mounts,
+			Err(_) => parse_mount_table(ctx, &ctx.proc_mounts, parse_proc_mounts_line)?,
+		};
+		// /proc/swaps can be missing or unreadable in a container, which is
+		// treated as there being no swap.
+		let swaps = parse_proc_swaps(&ctx.proc_swaps).unwrap_or_default();
+		Ok(Mounts {
+			mounts,
+			swaps: swaps.iter().map(|path| resolve_dev_path(&ctx.dev, path)).collect(),
+			dev: ctx.dev.clone(),
+		})
+	}
+
+	/// Whether `/dev/<name>` is in use as swap, under whichever of its
+	/// names it was enabled by.
+	fn is_swap(&self, name : &str) -> bool {
+		self.swaps.contains(&resolve_dev_path(&self.dev, &format!("/dev/{}", name)))
+	}
+
+	/// The mounts of `/dev/<name>`. Mounts of the whole filesystem come
+	/// before bind mounts of a directory within it, so the first entry is
+	/// the device's primary mountpoint.
+	fn of(&self, name : &str) -> Vec<&MountInfo> {
+		let path = resolve_dev_path(&self.dev, &format!("/dev/{}", name));
+		let mut entries : Vec<&MountInfo> = self.mounts.get(&path).map_or_else(Vec::new, |entries| entries.iter().collect());
+		entries.sort_by_key(|entry| entry.root != "/");
+		entries
+	}
+}
+
+fn read_partition_mountpoints(mounts : &Mounts, name : &str) -> Vec<String> {
+	let entries = mounts.of(name);
+	if entries.is_empty() && mounts.is_swap(name) {
+		vec![String::from("[SWAP]")]
+	} else {
+		entries.iter().map(|entry| entry.mountpoint.to_owned()).collect()
+	}
+}
+
+fn read_partition_mountpoint(mounts : &Mounts, name : &str) -> String {
+	read_partition_mountpoints(mounts, name).into_iter().next().unwrap_or_default()
+}
+
+fn read_partition_mount_options(mounts : &Mounts, name : &str) -> String {
+	mounts.of(name).first().map_or_else(String::new, |entry| entry.options())
+}
+
+/// The name under `/dev` that a device-mapper device is mounted by. This is
+/// usually its `/dev/mapper` symlink, but `/dev/dm-N` works too.
+fn dm_mount_name(mounts : &Mounts, name : &str, dm_name : &str) -> String {
+	let mapper = format!("mapper/{}", dm_name);
+	if read_partition_mountpoints(mounts, &mapper).is_empty() {
+		name.to_owned()
+	} else {
+		mapper
+	}
+}
+
+/// The entry names of a sysfs directory such as `slaves/`, which is absent
+/// for most devices.
+fn read_dir_names(path : &Path) -> Vec<String> {
+	match fs::read_dir(path) {
+		Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.file_name().to_string_lossy().into_owned()).collect(),
+		Err(_) => Vec::new(),
+	}
+}
+
+/// Reads the partition whose sysfs directory is `path`.
+fn read_partition(ctx : &Context, mounts : &Mounts, path : &Path, name : String) -> Option<Partition> {
+	let majmin = parse_block_file(path, "dev")?;
+	let removable = parse_block_file(path, "removable");
+	let size = read_size(ctx, path, &name);
+	let start = parse_block_file(path, "start");
+	let alignment_offset = parse_block_file(path, "alignment_offset");
+	let readonly = parse_block_file(path, "ro");
+	let meta = load_uevent_metadata(ctx, &majmin, &name);
+	let mountpoint = read_partition_mountpoint(mounts, &name);
+	let mountpoints = read_partition_mountpoints(mounts, &name);
+	let mount_options = read_partition_mount_options(mounts, &name);
+	let holders = read_dir_names(&path.join("holders"));
+	let node = read_device_node(ctx, &name);
+	let dev_path = read_dev_path(ctx, &name, None);
+	Some(Partition { name, removable, majmin, size, start, alignment_offset, readonly, holders, node, dev_path, metadata: meta, mountpoint, mountpoints, mount_options })
+}
+
+/// Reads the partitions of the disk at `path`. The kernel marks each
+/// partition's subdirectory with a `partition` attribute. A disk that is
+/// unplugged while it is being read gives whichever partitions were seen
+/// before it went away.
+fn read_partitions(ctx : &Context, mounts : &Mounts, path : &Path) -> Vec<Partition> {
+	let entries = match fs::read_dir(path) {
+		Ok(entries) => entries,
+		Err(_) => return Vec::new(),
+	};
+	entries.filter_map(|entry| entry.ok())
+		.filter(|entry| entry.path().join("partition").is_file())
+		.filter_map(|entry| read_partition(ctx, mounts, &entry.path(), entry.file_name().to_string_lossy().into_owned()))
+		.collect()
+}
+
+#[test]
+fn test_read_partitions_vanished() {
+	let ctx = Context::with_root("/nonexistent/lsblk");
+	let mounts = Mounts { mounts: HashMap::new(), swaps: HashSet::new(), dev: ctx.dev.clone() };
+	assert!(read_partitions(&ctx, &mounts, Path::new("/nonexistent/lsblk/sys/block/sdz")).is_empty());
+}
+
+/// Buses whose devices can be attached and detached while running.
+const HOTPLUG_SUBSYSTEMS : &[&str] = &["usb", "ieee1394", "pcmcia", "thunderbolt", "sdio", "memstick"];
+
+/// Whether the block device at `path` is hotpluggable. The flag lives on the
+/// bus device rather than the disk, so this follows `path` into
+/// `/sys/devices` and walks up its parents, looking for one marked
+/// `removable` or belonging to a hotplug bus.
+fn read_hotplug(ctx : &Context, path : &Path) -> Option<u64> {
+	let sys_root = ctx.sys_block.parent()?.canonicalize().ok()?;
+	let path = path.canonicalize().ok()?;
+	for dir in path.ancestors().take_while(|&dir| dir.starts_with(&sys_root) && dir != sys_root) {
+		let removable : Option<String> = parse_block_file(dir, "removable");
+		if removable.as_deref() == Some("removable") {
+			return Some(1);
+		}
+		let subsystem = fs::read_link(dir.join("subsystem")).ok();
+		let subsystem = subsystem.as_ref().and_then(|subsystem| subsystem.file_name()).and_then(|name| name.to_str());
+		if subsystem.is_some_and(|subsystem| HOTPLUG_SUBSYSTEMS.contains(&subsystem)) {
+			return Some(1);
+		}
+	}
+	Some(0)
+}
+
+/// The amount of data stored in a zram device, in bytes.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZramStat {
+	/// The size of the data before compression.
+	pub data : u64,
+	/// The space the data takes up once compressed.
+	pub compressed : u64,
+}
+
+/// Parses a zram device's `mm_stat`, whose first two fields are the
+/// original and compressed data sizes. Later fields vary between kernels.
+fn parse_mm_stat(line : &str) -> Option<ZramStat> {
+	let mut fields = line.split_whitespace().map(|field| field.parse::<u64>());
+	match (fields.next(), fields.next()) {
+		(Some(Ok(data)), Some(Ok(compressed))) => Some(ZramStat { data, compressed }),
+		_ => None,
+	}
+}
+
+#[test]
+fn test_parse_mm_stat() {
+	assert!(parse_mm_stat("  6807552  1467417  2306048        0  2306048     1038        0        0") ==
+		Some(ZramStat { data: 6807552, compressed: 1467417 }));
+	assert!(parse_mm_stat("4096 74") == Some(ZramStat { data: 4096, compressed: 74 }));
+	assert!(parse_mm_stat("4096").is_none());
+	assert!(parse_mm_stat("").is_none());
+}
+
+/// Picks the active scheduler out of `queue/scheduler`, which lists every
+/// available one with the active one in brackets, e.g.
+/// `[mq-deadline] kyber none`. Devices with no choice just give `none`.
+fn parse_scheduler(line : &str) -> Option<String> {
+	let mut schedulers = line.split_whitespace();
+	match schedulers.clone().find(|name| name.starts_with('[') && name.ends_with(']')) {
+		Some(active) => Some(active[1..active.len()-1].to_owned()),
+		None => match (schedulers.next(), schedulers.next()) {
+			(Some(only), None) => Some(only.to_owned()),
+			_ => None,
+		},
+	}
+}
+
+#[test]
+fn test_parse_scheduler() {
+	assert!(parse_scheduler("[mq-deadline] kyber bfq none") == Some("mq-deadline".to_owned()));
+	assert!(parse_scheduler("mq-deadline kyber [bfq] none") == Some("bfq".to_owned()));
+	assert!(parse_scheduler("[none] mq-deadline") == Some("none".to_owned()));
+	assert!(parse_scheduler("none") == Some("none".to_owned()));
+	assert!(parse_scheduler("mq-deadline none").is_none());
+	assert!(parse_scheduler("").is_none());
+}
+
+fn block_transport(name : &str, meta : Option<&BlockMetadata>) -> Option<String> {
+	match meta.and_then(|meta| meta.id_bus.as_ref()) {
+		Some(bus) => Some(bus.to_owned()),
+		None if name.starts_with("nvme") => Some(String::from("nvme")),
+		None => None,
+	}
+}
+
+#[test]
+fn test_block_transport() {
+	let usb = BlockMetadata { id_bus: Some("usb".to_string()), ..BlockMetadata::default() };
+	assert!(block_transport("sdb", Some(&usb)) == Some("usb".to_string()));
+	assert!(block_transport("nvme0n1", None) == Some("nvme".to_string()));
+	assert!(block_transport("nvme0n1", Some(&BlockMetadata::default())) == Some("nvme".to_string()));
+	assert!(block_transport("sda", Some(&BlockMetadata::default())).is_none());
+	assert!(block_transport("dm-0", None).is_none());
+}
+
+fn read_block(ctx : &Context, mounts : &Mounts, dir : DirEntry) -> Option<Block> {
+	// The entries in /sys/block are symlinks into /sys/devices. Every
+	// attribute is read from the device's own directory, so that relative
+	// links such as `device` and those in `slaves` and `holders` resolve
+	// from where the kernel put them. The name is still the entry's.
+	let path = dir.path().canonicalize().unwrap_or_else(|_| dir.path());
+	let name = dir.file_name().to_string_lossy().into_owned();
+	read_device(ctx, mounts, &path, name).map(|block| Block { partitions: read_partitions(ctx, mounts, &path), ..block })
+}
+
+/// Reads the whole device whose sysfs directory is `path`, leaving its
+/// partitions for the caller to find.
+fn read_device(ctx : &Context, mounts : &Mounts, path : &Path, name : String) -> Option<Block> {
+	let majmin : Option<MajorMinor> = parse_block_file(path, "dev");
+	match majmin {
+		Some(majmin) => {
+			let removable = parse_block_file(path, "removable");
+			let hotplug = read_hotplug(ctx, path);
+			let size = read_size(ctx, path, &name);
+			let readonly = parse_block_file(path, "ro");
+			let rotational = parse_block_file(path, "queue/rotational");
+			let logical_block_size = parse_block_file(path, "queue/logical_block_size");
+			let physical_block_size = parse_block_file(path, "queue/physical_block_size");
+			let alignment_offset = parse_block_file(path, "alignment_offset");
+			let minimum_io_size = parse_block_file(path, "queue/minimum_io_size");
+			let optimal_io_size = parse_block_file(path, "queue/optimal_io_size");
+			let discard_granularity = parse_block_file(path, "queue/discard_granularity");
+			let discard_max_bytes = parse_block_file(path, "queue/discard_max_bytes");
+			let dax = parse_block_file(path, "queue/dax");
+			let scheduler = parse_block_file::<String>(path, "queue/scheduler").and_then(|line| parse_scheduler(&line));
+			let meta = load_uevent_metadata(ctx, &majmin, &name);
+			let transport = block_transport(&name, meta.as_ref());
+			let state = parse_block_file(path, "device/state");
+			let dm_name : Option<String> = parse_block_file(path, "dm/name");
+			let backing_file = parse_block_file(path, "loop/backing_file");
+			let zram = if name.starts_with("zram") {
+				parse_block_file::<String>(path, "mm_stat").and_then(|line| parse_mm_stat(&line))
+			} else {
+				None
+			};
+			let raid_level = parse_block_file(path, "md/level");
+			let slaves = read_dir_names(&path.join("slaves"));
+			let holders = read_dir_names(&path.join("holders"));
+			let node = read_device_node(ctx, &name);
+			let dev_path = read_dev_path(ctx, &name, dm_name.as_deref());
+			// A filesystem can be made on a whole disk as well as on a
+			// partition, as with `mkfs.ext4 /dev/sdb`.
+			let mount_name = match dm_name {
+				Some(ref dm_name) => dm_mount_name(mounts, &name, dm_name),
+				None => name.clone(),
+			};
+			let mountpoint = read_partition_mountpoint(mounts, &mount_name);
+			let mountpoints = read_partition_mountpoints(mounts, &mount_name);
+			let mount_options = read_partition_mount_options(mounts, &mount_name);
+			Some(Block { name, removable, hotplug, majmin, size, readonly, rotational, logical_block_size, physical_block_size, alignment_offset, minimum_io_size, optimal_io_size, discard_granularity, discard_max_bytes, dax, scheduler, transport, state, partitions: Vec::new(), dm_name, raid_level, backing_file, zram, slaves, holders, node, dev_path, metadata: meta, mountpoint, mountpoints, mount_options })
+		},
+		_ => None,
+	}
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+struct KeyValue<'a> {
+	key : &'a str,
+	value : &'a str,
+}
+
+fn parse_line(line : &str) -> Option<KeyValue<'_>> {
+	UEVENT_LINE_RE.captures(line).map(|caps| {
+		KeyValue { key : caps.at(1).unwrap(), value : caps.at(2).unwrap() }
+	})
+}
+
+#[test]
+fn test_parse_line() {
+	assert!(parse_line("E:ID_ATA_FEATURE_SET_PM=1") ==
+		Some(KeyValue { key:"ID_ATA_FEATURE_SET_PM", value: "1"}));
+
+	assert!(parse_line("E:KEY=one two three") ==
+		Some(KeyValue { key:"KEY", value: "one two three"}));
+
+	assert!(parse_line("W:12").is_none());
+	assert!(parse_line("E:ID_ATA_FEATURE_SET_PM").is_none());
+	assert!(parse_line("E:ID_ATA_FEATURE_SET_PM=1=1") ==
+		Some(KeyValue { key:"ID_ATA_FEATURE_SET_PM", value: "1=1"}));
+	assert!(parse_line("E:ID_FS_LABEL=").is_none());
+}
+
+fn parse_uevent_metadata(data : &str) -> Option<BlockMetadata> {
+	let mut id_type = None;
+	let mut meta = BlockMetadata::default();
+
+	for kv in data.lines().map(parse_line) {
+		match kv {
+			Some(KeyValue { key:"ID_TYPE", value }) => {
+				id_type = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_FS_TYPE", value }) => {
+				meta.id_fs_type = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_FS_UUID", value }) => {
+				meta.id_fs_uuid = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_FS_LABEL", value }) => {
+				meta.id_fs_label = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_PART_ENTRY_NAME", value }) => {
+				meta.id_part_entry_name = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_PART_ENTRY_UUID", value }) => {
+				meta.id_part_entry_uuid = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_PART_ENTRY_TYPE", value }) => {
+				meta.id_part_entry_type = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_MODEL", value }) => {
+				meta.id_model = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_SERIAL_SHORT", value }) => {
+				meta.id_serial_short = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_SERIAL", value }) => {
+				meta.id_serial = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_VENDOR", value }) => {
+				meta.id_vendor = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_BUS", value }) => {
+				meta.id_bus = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_WWN", value }) => {
+				meta.id_wwn = Some(value.to_owned())
+			},
+			Some(KeyValue { key:"ID_WWN_WITH_EXTENSION", value }) => {
+				meta.id_wwn_with_extension = Some(value.to_owned())
+			},
+			_ => {}
+		}
+	}
+
+	id_type.map(|id_type| BlockMetadata { id_type, ..meta })
+}
+
+#[test]
+fn test_parse_uevent_metadata() {
+	assert!(
+		parse_uevent_metadata("E:ID_TYPE=disk") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_fs_type: None,
+			id_fs_uuid: None,
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_TYPE=disk\nE:ID_FS_TYPE=ext4") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_fs_type: Some("ext4".to_string()),
+			id_fs_uuid: None,
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_FS_TYPE=ext4").is_none()
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_TYPE=disk\nE:ID_FS_UUID=eca1e7f9-42c7-49b7-9f42-bec0c3e975e6") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_fs_type: None,
+			id_fs_uuid: Some("eca1e7f9-42c7-49b7-9f42-bec0c3e975e6".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("\
+			S:disk/by-label/boot\n\
+			W:1\n\
+			E:ID_TYPE=disk\n\
+			E:ID_VENDOR=ATA\n\
+			E:ID_MODEL=Samsung_SSD_850_EVO_250GB\n\
+			E:ID_SERIAL=Samsung_SSD_850_EVO_250GB_S21PNXAG441234A\n\
+			E:ID_SERIAL_SHORT=S21PNXAG441234A\n\
+			E:ID_FS_LABEL=boot\n\
+			E:ID_FS_TYPE=ext4\n\
+			E:ID_PART_ENTRY_NAME=Linux\n\
+			G:systemd\n") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_fs_type: Some("ext4".to_string()),
+			id_fs_uuid: None,
+			id_fs_label: Some("boot".to_string()),
+			id_part_entry_name: Some("Linux".to_string()),
+			id_part_entry_uuid: None,
+			id_part_entry_type: None,
+			id_model: Some("Samsung_SSD_850_EVO_250GB".to_string()),
+			id_serial_short: Some("S21PNXAG441234A".to_string()),
+			id_serial: Some("Samsung_SSD_850_EVO_250GB_S21PNXAG441234A".to_string()),
+			id_vendor: Some("ATA".to_string()),
+			id_bus: None,
+			id_wwn: None,
+			id_wwn_with_extension: None,
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("I:1718112345\nE:ID_TYPE=disk\nE:ID_FS_LABEL=a=b==\nG:systemd\nQ:systemd\nV:1") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_fs_label: Some("a=b==".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_TYPE=disk\nE:ID_WWN=0x5002538d4000abcd\nE:ID_WWN_WITH_EXTENSION=0x5002538d4000abcd") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_wwn: Some("0x5002538d4000abcd".to_string()),
+			id_wwn_with_extension: Some("0x5002538d4000abcd".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_TYPE=disk\nE:ID_BUS=usb") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_bus: Some("usb".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_TYPE=partition\nE:ID_PART_ENTRY_TYPE=c12a7328-f81f-11d2-ba4b-00a0c93ec93b") ==
+		Some(BlockMetadata {
+			id_type: "partition".to_string(),
+			id_part_entry_type: Some("c12a7328-f81f-11d2-ba4b-00a0c93ec93b".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("\
+			E:ID_TYPE=partition\n\
+			E:ID_PART_ENTRY_SCHEME=gpt\n\
+			E:ID_PART_ENTRY_UUID=8f3b6d2c-5e1a-4c7b-9d0e-2a4f6b8c1d3e\n\
+			E:ID_PART_ENTRY_NAME=EFI\n\
+			E:ID_FS_UUID=1A2B-3C4D\n") ==
+		Some(BlockMetadata {
+			id_type: "partition".to_string(),
+			id_fs_uuid: Some("1A2B-3C4D".to_string()),
+			id_part_entry_name: Some("EFI".to_string()),
+			id_part_entry_uuid: Some("8f3b6d2c-5e1a-4c7b-9d0e-2a4f6b8c1d3e".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+
+	assert!(
+		parse_uevent_metadata("E:ID_BUS=ata\nE:ID_TYPE=disk\nE:ID_ATA=1") ==
+		Some(BlockMetadata {
+			id_type: "disk".to_string(),
+			id_bus: Some("ata".to_string()),
+			..BlockMetadata::default()
+		})
+	);
+}
+
+fn read_udev_file(path : &Path) -> io::Result<String> {
+	fs::read_to_string(path)
+}
+
+fn read_uevent_metadata(ctx : &Context, device : &MajorMinor) -> Option<BlockMetadata> {
+	let path = device.udev_path(&ctx.udev_data);
+	let contents = none!((ctx.read_udev_data)(&path));
+	parse_uevent_metadata(&contents)
+}
+
+/// Finds the filesystem on `/dev/<name>` from its superblock, as blkid
+/// would.
+fn probe_metadata(ctx : &Context, name : &str) -> Option<BlockMetadata> {
+	let mut dev = none!(File::open(ctx.dev.join(name)));
+	probe::probe(&mut dev).map(|probe| BlockMetadata {
+		id_fs_type: Some(probe.fs_type.to_owned()),
+		id_fs_uuid: probe.uuid,
+		..BlockMetadata::default()
+	})
+}
+
+fn load_uevent_metadata(ctx : &Context, device : &MajorMinor, name : &str) -> Option<BlockMetadata> {
+	ctx.udev_metadata(device, || {
+		read_uevent_metadata(ctx, device).or_else(|| if ctx.probe { probe_metadata(ctx, name) } else { None })
+	})
+}
+
+/// Finds the btrfs filesystems that span more than one device, which udev
+/// reports as several devices sharing an `ID_FS_UUID`. The result maps each
+/// such UUID to the kernel names of its devices, in sorted order.
+pub fn multi_device_filesystems(blocks : &[Block]) -> HashMap<String, Vec<String>> {
+	let devices = blocks.iter().flat_map(|block| {
+		Some((&block.name, &block.metadata)).into_iter()
+			.chain(block.partitions.iter().map(|part| (&part.name, &part.metadata)))
+	});
+
+	let mut filesystems = HashMap::new();
+	for (name, meta) in devices {
+		if let Some(meta) = meta.as_ref().filter(|meta| meta.id_fs_type.as_deref() == Some("btrfs")) {
+			if let Some(ref uuid) = meta.id_fs_uuid {
+				filesystems.entry(uuid.to_owned()).or_insert_with(Vec::new).push(name.to_owned());
+			}
+		}
+	}
+
+	filesystems.retain(|_, members : &mut Vec<String>| members.len() > 1);
+	for members in filesystems.values_mut() {
+		members.sort();
+	}
+	filesystems
+}
+
+/// Scans `/sys/block` and returns every block device along with its
+/// partitions.
+pub fn list_block_devices() -> Result<Vec<Block>, LsblkError> {
+	list_block_devices_in(&Context::default())
+}
+
+/// Like `list_block_devices`, but reads from the paths in `ctx`.
+pub fn list_block_devices_in(ctx : &Context) -> Result<Vec<Block>, LsblkError> {
+	iter_block_devices_in(ctx).collect()
+}
+
+/// Scans `/sys/block` lazily, reading each block device only when the
+/// iterator reaches it, so a caller can filter early or stop part way.
+/// The only error is a failure to start the scan, which is yielded on its
+/// own.
+pub fn iter_block_devices() -> impl Iterator<Item = Result<Block, LsblkError>> {
+	iter_block_devices_in(&Context::default())
+}
+
+/// Like `iter_block_devices`, but reads from the paths in `ctx`.
+pub fn iter_block_devices_in(ctx : &Context) -> impl Iterator<Item = Result<Block, LsblkError>> {
+	let ctx = ctx.clone();
+	let scan = fs::read_dir(&ctx.sys_block)
+		.map_err(|err| sysfs_error(&ctx.sys_block, err))
+		.and_then(|block_dirs| Ok((block_dirs, Mounts::load(&ctx)?)));
+	let (scan, error) = match scan {
+		Ok(scan) => (Some(scan), None),
+		Err(err) => (None, Some(err)),
+	};
+	let blocks = scan.into_iter().flat_map(move |(block_dirs, mounts)| {
+		let ctx = ctx.clone();
+		// Entries that vanish part way through the scan are skipped.
+		block_dirs
+			.filter_map(|dir| dir.ok())
+			.filter_map(move |dir| read_block(&ctx, &mounts, dir))
+	});
+	error.into_iter().map(Err).chain(blocks.map(Ok))
+}
+
+/// Like `list_block_devices`, but finds the devices through the
+/// `/sys/dev/block/MAJ:MIN` links, which cover every partition as well as
+/// every whole device, rather than by scanning the directories in
+/// `/sys/block`.
+pub fn list_block_devices_by_number() -> Result<Vec<Block>, LsblkError> {
+	list_block_devices_by_number_in(&Context::default())
+}
+
+/// Like `list_block_devices_by_number`, but reads from the paths in `ctx`.
+/// Each link is followed to the device's directory, and a device with a
+/// `partition` attribute is given to the device whose directory holds it.
+/// `slaves` and `holders` are read as usual, for the devices built on
+/// others. A partition whose disk has no link of its own is left out.
+pub fn list_block_devices_by_number_in(ctx : &Context) -> Result<Vec<Block>, LsblkError> {
+	let links = fs::read_dir(&ctx.sys_dev_block).map_err(|err| sysfs_error(&ctx.sys_dev_block, err))?;
+	let mounts = Mounts::load(ctx)?;
+	let mut blocks = Vec::new();
+	let mut partitions = Vec::new();
+	// Links that vanish part way through the scan are skipped.
+	for link in links.filter_map(|link| link.ok()) {
+		let path = match link.path().canonicalize() {
+			Ok(path) => path,
+			Err(_) => continue,
+		};
+		let name = match path.file_name() {
+			Some(name) => name.to_string_lossy().into_owned(),
+			None => continue,
+		};
+		if path.join("partition").is_file() {
+			if let (Some(disk), Some(part)) = (path.parent(), read_partition(ctx, &mounts, &path, name)) {
+				partitions.push((disk.to_owned(), part));
+			}
+		} else if let Some(block) = read_device(ctx, &mounts, &path, name) {
+			blocks.push((path, block));
+		}
+	}
+	for (disk, part) in partitions {
+		if let Some((_, block)) = blocks.iter_mut().find(|(path, _)| *path == disk) {
+			block.partitions.push(part);
+		}
+	}
+	Ok(blocks.into_iter().map(|(_, block)| block).collect())
+}
@@ -1,455 +1,2526 @@
-extern crate regex;
+extern crate libc;
+extern crate lsblk;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+extern crate unicode_width;
 
-use std::fmt;
-use std::collections::HashMap;
+mod column;
+mod config;
+mod filter;
+mod json;
+mod options;
+#[cfg(feature = "serde")]
+mod snapshot;
+mod statvfs;
+mod uevent;
+mod users;
+
+use std::cmp::Ordering;
 use std::collections::HashSet;
-use std::fs;
-use std::fs::DirEntry;
-use std::fs::File;
-use std::io::Error;
-use std::io::Read;
+use std::env;
+use std::error;
+use std::io;
 use std::io::ErrorKind;
+use std::io::Write;
+use std::mem;
+#[cfg(feature = "serde")]
 use std::path::Path;
 use std::path::PathBuf;
-use regex::Regex;
-use std::str::FromStr;
+use std::process;
+use std::slice;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic;
+use std::sync::atomic::AtomicBool;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use column::Align;
+use column::CellFormat;
+use column::Column;
+use json::Json;
+use options::ColorMode;
+use options::Options;
+use uevent::Action;
+use lsblk::Block;
+use lsblk::BlockMetadata;
+use lsblk::DeviceNode;
+use lsblk::MajorMinor;
+#[cfg(test)]
+use lsblk::Partition;
+use lsblk::ZramStat;
+use statvfs::FsUsage;
+use unicode_width::UnicodeWidthChar;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+enum BlockType { Disk, Partition, DeviceMapper, Crypt, Raid }
 
-macro_rules! invalid {
-	($x:expr, $msg:expr) => ($x.ok_or(Error::new(ErrorKind::InvalidData, $msg)))
+const BLOCK_TYPES : &[BlockType] = &[BlockType::Disk, BlockType::Partition, BlockType::DeviceMapper, BlockType::Crypt, BlockType::Raid];
+
+fn describe_block_type(blocktype : BlockType) -> &'static str {
+	match blocktype {
+		BlockType::Disk => "disk",
+		BlockType::Partition => "part",
+		BlockType::DeviceMapper => "dm",
+		BlockType::Crypt => "crypt",
+		BlockType::Raid => "md",
+	}
 }
 
-macro_rules! none {
-	($x:expr) => (match $x {
-		Ok(y) => y,
-		_ => return None
+/// The device type named as in the TYPE column, such as `crypt`.
+fn parse_block_type(name : &str) -> Result<BlockType, String> {
+	BLOCK_TYPES.iter().cloned().find(|&blocktype| describe_block_type(blocktype) == name).ok_or_else(|| {
+		let names = BLOCK_TYPES.iter().map(|&blocktype| describe_block_type(blocktype)).collect::<Vec<_>>();
+		format!("unknown device type '{}' (valid types: {})", name, names.join(", "))
 	})
 }
 
-#[derive(Debug)]
-struct MajorMinor {
-	major : u8,
-	minor : u8,
+#[test]
+fn test_parse_block_type() {
+	assert!(parse_block_type("crypt") == Ok(BlockType::Crypt));
+	assert!(parse_block_type("part") == Ok(BlockType::Partition));
+	assert!(parse_block_type("lvm") == Err("unknown device type 'lvm' (valid types: disk, part, dm, crypt, md)".to_owned()));
 }
 
-impl MajorMinor {
-	fn udev_path(&self) -> PathBuf {
-		let filename = format!("b{}", self);
-		PathBuf::from("/run/udev/data").join(filename)
+#[derive(Clone)]
+struct Row {
+	name: String,
+	kname : String,
+	majmin: MajorMinor,
+	removable: Option<u64>,
+	hotplug: Option<u64>,
+	size: Option<u64>,
+	/// The first sector of a partition.
+	start : Option<u64>,
+	readonly: Option<u64>,
+	rotational: Option<u64>,
+	logical_block_size : Option<u64>,
+	physical_block_size : Option<u64>,
+	alignment_offset : Option<i64>,
+	minimum_io_size : Option<u64>,
+	optimal_io_size : Option<u64>,
+	discard_granularity : Option<u64>,
+	discard_max_bytes : Option<u64>,
+	dax : Option<u64>,
+	scheduler : Option<String>,
+	transport : Option<String>,
+	backing_file : Option<String>,
+	zram : Option<ZramStat>,
+	raid_level : Option<String>,
+	state : Option<String>,
+	row_type: BlockType,
+	metadata : Option<BlockMetadata>,
+	mountpoint : String,
+	mountpoints : Vec<String>,
+	mount_options : String,
+	fs_usage : Option<FsUsage>,
+	/// Every device in this device's filesystem, when it spans several.
+	fs_members : Vec<String>,
+	slaves : Vec<String>,
+	holders : Vec<String>,
+	node : Option<DeviceNode>,
+	dev_path : Option<PathBuf>,
+	children : Vec<Row>,
+	/// Whether `children` were cut off by `--tree-depth`.
+	truncated : bool,
+}
+
+fn mountpoint_usage(mountpoint : &str) -> Option<FsUsage> {
+	if mountpoint.is_empty() || mountpoint == "[SWAP]" {
+		None
+	} else {
+		statvfs::fs_usage(mountpoint)
 	}
 }
 
-impl fmt::Display for MajorMinor {
-	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}:{}", self.major, self.minor)
+/// The kernel names of the devices holding a LUKS volume. A device-mapper
+/// device built on one of these is the opened volume.
+fn luks_devices(blocks : &[Block]) -> Vec<String> {
+	let is_luks = |meta : &Option<BlockMetadata>| {
+		meta.as_ref().and_then(|meta| meta.id_fs_type.as_ref()).is_some_and(|fs_type| fs_type == "crypto_LUKS")
+	};
+	let mut luks = Vec::new();
+	for block in blocks {
+		if is_luks(&block.metadata) {
+			luks.push(block.name.to_owned());
+		}
+		luks.extend(block.partitions.iter().filter(|part| is_luks(&part.metadata)).map(|part| part.name.to_owned()));
 	}
+	luks
 }
 
-impl FromStr for MajorMinor {
-	type Err = Error;
-	fn from_str(s: &str) -> Result<MajorMinor, Error> {
-		let re = Regex::new(r"^([0-9]+):([0-9]+)$").unwrap();
+/// Builds the rows to print. `statvfs` is only called on mountpoints when
+/// `with_usage` is set, as it can block on unresponsive network filesystems.
+fn block_rows(blocks : &[Block], with_usage : bool) -> Vec<Row> {
+	let usage = |mountpoint : &str| if with_usage { mountpoint_usage(mountpoint) } else { None };
+	let filesystems = lsblk::multi_device_filesystems(blocks);
+	let luks = luks_devices(blocks);
+	let fs_members = |meta : &Option<BlockMetadata>| {
+		meta.as_ref().and_then(|meta| meta.id_fs_uuid.as_ref()).and_then(|uuid| filesystems.get(uuid)).cloned().unwrap_or_default()
+	};
 
-		invalid!(re.captures(s).map(|caps| {
-			MajorMinor {
-				major: caps.at(1).unwrap().parse::<u8>().unwrap(),
-				minor: caps.at(2).unwrap().parse::<u8>().unwrap(),
+	blocks.iter().map(|block| {
+		let children = block.partitions.iter().map(|part| {
+			Row {
+				name: part.name.to_owned(),
+				kname: part.name.to_owned(),
+				majmin: part.majmin,
+				removable: block.removable,
+				hotplug: block.hotplug,
+				size: part.size,
+				start: part.start,
+				readonly: part.readonly,
+				rotational: block.rotational,
+				logical_block_size: block.logical_block_size,
+				physical_block_size: block.physical_block_size,
+				alignment_offset: part.alignment_offset,
+				minimum_io_size: block.minimum_io_size,
+				optimal_io_size: block.optimal_io_size,
+				discard_granularity: block.discard_granularity,
+				discard_max_bytes: block.discard_max_bytes,
+				dax: block.dax,
+				scheduler: block.scheduler.clone(),
+				transport: None,
+				backing_file: None,
+				zram: None,
+				raid_level: None,
+				state: None,
+				row_type: BlockType::Partition,
+				metadata: part.metadata.clone(),
+				mountpoint: part.mountpoint.to_owned(),
+				mountpoints: part.mountpoints.clone(),
+				mount_options: part.mount_options.to_owned(),
+				fs_usage: usage(&part.mountpoint),
+				fs_members: fs_members(&part.metadata),
+				slaves: Vec::new(),
+				holders: part.holders.clone(),
+				node: part.node,
+				dev_path: part.dev_path.clone(),
+				children: Vec::new(),
+				truncated: false,
 			}
-		}), "MajorMinor::from_str")
+		}).collect();
+
+		Row {
+			name: block.dm_name.as_ref().unwrap_or(&block.name).to_owned(),
+			kname: block.name.to_owned(),
+			majmin: block.majmin,
+			removable: block.removable,
+			hotplug: block.hotplug,
+			size: block.size,
+			start: None,
+			readonly: block.readonly,
+			rotational: block.rotational,
+			logical_block_size: block.logical_block_size,
+			physical_block_size: block.physical_block_size,
+			alignment_offset: block.alignment_offset,
+			minimum_io_size: block.minimum_io_size,
+			optimal_io_size: block.optimal_io_size,
+			discard_granularity: block.discard_granularity,
+			discard_max_bytes: block.discard_max_bytes,
+			dax: block.dax,
+			scheduler: block.scheduler.clone(),
+			transport: block.transport.clone(),
+			backing_file: block.backing_file.clone(),
+			zram: block.zram,
+			raid_level: block.raid_level.clone(),
+			state: block.state.clone(),
+			row_type: match block.dm_name {
+				Some(_) if block.slaves.iter().any(|slave| luks.contains(slave)) => BlockType::Crypt,
+				Some(_) => BlockType::DeviceMapper,
+				None if block.raid_level.is_some() => BlockType::Raid,
+				None => BlockType::Disk,
+			},
+			metadata: block.metadata.clone(),
+			mountpoint: block.mountpoint.to_owned(),
+			mountpoints: block.mountpoints.clone(),
+			mount_options: block.mount_options.to_owned(),
+			fs_usage: usage(&block.mountpoint),
+			fs_members: fs_members(&block.metadata),
+			slaves: block.slaves.clone(),
+			holders: block.holders.clone(),
+			node: block.node,
+			dev_path: block.dev_path.clone(),
+			children,
+			truncated: false,
+		}
+	}).collect()
+}
+
+#[test]
+fn test_block_rows_fs_usage() {
+	// A disk formatted whole, with no partition table, mounted on /.
+	let mut block = test_block();
+	block.partitions.clear();
+	block.mountpoint = "/".to_owned();
+	let rows = block_rows(&[block.clone()], true);
+	assert!(rows[0].fs_usage.is_some_and(|usage| usage.size > 0));
+	assert!(!Column::FsUse.text(&rows[0], &CellFormat::default()).trim().is_empty());
+	assert!(block_rows(&[block.clone()], false)[0].fs_usage.is_none());
+
+	block.mountpoint = "[SWAP]".to_owned();
+	assert!(block_rows(&[block], true)[0].fs_usage.is_none());
+}
+
+fn subtree(nodes : &[Row], edges : &[(String, String)], node : &Row, path : &mut Vec<String>) -> Row {
+	let mut row = node.clone();
+	path.push(row.kname.to_owned());
+	row.children = edges.iter()
+		.filter(|&(parent, child)| *parent == row.kname && !path.contains(child))
+		.filter_map(|(_, child)| nodes.iter().find(|node| node.kname == *child))
+		.collect::<Vec<_>>()
+		.into_iter()
+		.map(|child| subtree(nodes, edges, child, path))
+		.collect();
+	path.pop();
+	row
+}
+
+/// Flattens the rows into a graph of devices, with an edge from each
+/// device to every device built on top of it. A disk holds its partitions,
+/// and a device such as a device-mapper volume is held by each device in its
+/// `slaves/` or naming it in `holders/`.
+fn device_graph(rows : Vec<Row>) -> (Vec<Row>, Vec<(String, String)>) {
+	let mut nodes = Vec::new();
+	let mut edges = Vec::new();
+	for mut row in rows {
+		let children = mem::take(&mut row.children);
+		for child in &children {
+			edges.push((row.kname.to_owned(), child.kname.to_owned()));
+		}
+		nodes.push(row);
+		nodes.extend(children);
 	}
+
+	for node in &nodes {
+		let links = node.slaves.iter().map(|slave| (slave.to_owned(), node.kname.to_owned()))
+			.chain(node.holders.iter().map(|holder| (node.kname.to_owned(), holder.to_owned())));
+		for link in links {
+			if !edges.contains(&link) {
+				edges.push(link);
+			}
+		}
+	}
+	edges.retain(|(parent, child)| {
+		nodes.iter().any(|node| node.kname == *parent) && nodes.iter().any(|node| node.kname == *child)
+	});
+
+	(nodes, edges)
 }
 
-#[derive(Debug)]
-#[derive(PartialEq)]
-struct BlockMetadata {
-	id_type : String,
-	id_fs_type : Option<String>,
-	id_fs_uuid : Option<String>,
+/// Devices without a parent become the roots, each with a copy of the
+/// subtree beneath it.
+fn graph_tree(nodes : &[Row], edges : &[(String, String)]) -> Vec<Row> {
+	nodes.iter()
+		.filter(|node| !edges.iter().any(|(_, child)| *child == node.kname))
+		.map(|node| subtree(nodes, edges, node, &mut Vec::new()))
+		.collect()
 }
 
-#[derive(Debug)]
-struct Partition {
-	name : String,
-	majmin : MajorMinor,
-	removable : Option<u64>,
-	size : Option<u64>,
-	readonly : Option<u64>,
+/// Arranges the rows into a tree from the links between devices in sysfs.
+/// A device is nested under every device it is built on, so it appears more
+/// than once when it has several. Devices whose parents are not listed stay
+/// at the top.
+fn build_tree(rows : Vec<Row>) -> Vec<Row> {
+	let (nodes, edges) = device_graph(rows);
+	graph_tree(&nodes, &edges)
+}
 
-	metadata : Option<BlockMetadata>,
-	mountpoint : String,
+/// The tree upside down: the devices nothing else is built on, such as
+/// filesystems and logical volumes, are at the top, with the devices they
+/// are built on beneath them, down to the disks.
+fn build_inverse_tree(rows : Vec<Row>) -> Vec<Row> {
+	let (nodes, edges) = device_graph(rows);
+	let edges = edges.into_iter().map(|(parent, child)| (child, parent)).collect::<Vec<_>>();
+	graph_tree(&nodes, &edges)
 }
 
-#[derive(Debug)]
-struct Block {
-	name : String,
-	majmin : MajorMinor,
-	removable : Option<u64>,
-	size : Option<u64>,
-	readonly : Option<u64>,
-	partitions : Vec<Partition>,
-	mountpoint : String,
+/// Sorts the rows by the first of `columns`, breaking ties with the ones
+/// after it, and in descending order with `reverse`. Only siblings are
+/// reordered, so every row stays beneath its parent.
+fn sort_rows(rows : &mut [Row], columns : &[Column], reverse : bool) {
+	rows.sort_by(|a, b| {
+		let order = columns.iter().fold(Ordering::Equal, |order, column| order.then_with(|| column.compare(a, b)));
+		if reverse { order.reverse() } else { order }
+	});
+	for row in rows {
+		sort_rows(&mut row.children, columns, reverse);
+	}
 }
 
-fn parse_block_file<T: FromStr>(path : &Path, filename : &str) -> Option<T> {
-	let filepath = PathBuf::from(path).join(filename);
-	let mut file = none!(File::open(filepath));
+/// The subtrees rooted at each of the devices `knames`, in that order.
+fn select_rows(rows : &[Row], knames : &[String]) -> Vec<Row> {
+	knames.iter().filter_map(|kname| find_row(rows, kname)).cloned().collect()
+}
 
-	let contents = &mut String::new();
-	let _ = file.read_to_string(contents).unwrap();
-	T::from_str(contents.trim()).ok()
+#[test]
+fn test_select_rows() {
+	let blocks = [
+		test_block(),
+		test_dm_block("dm-0", 0, "mirror", &["sda1", "sda2"]),
+		test_dm_block("dm-1", 1, "vg-home", &[]),
+	];
+	let rows = build_tree(block_rows(&blocks, false));
+	let tree = |rows : &[Row]| rows.iter().map(|row| {
+		let children = row.children.iter().map(|child| child.name.to_owned()).collect::<Vec<_>>();
+		format!("{}({})", row.name, children.join(","))
+	}).collect::<Vec<_>>();
+	assert!(tree(&select_rows(&rows, &["dm-1".to_owned(), "sda1".to_owned()])) == ["vg-home()", "sda1(mirror)"]);
+	assert!(tree(&select_rows(&rows, &["sda".to_owned()])) == ["sda(sda1,sda2)"]);
+	assert!(select_rows(&rows, &["sdz".to_owned()]).is_empty());
 }
 
-fn parse_sector_file(path : &Path, filename : &str) -> Option<u64> {
-	parse_block_file::<u64>(path, filename).map(|x| x*512)
+/// Drops the rows more than `depth` levels below the top of the tree,
+/// marking the rows that lost their children.
+fn limit_depth(rows : &mut [Row], depth : usize) {
+	for row in rows {
+		if depth == 0 {
+			row.truncated = !row.children.is_empty();
+			row.children.clear();
+		} else {
+			limit_depth(&mut row.children, depth - 1);
+		}
+	}
 }
 
-fn parse_proc_mounts_line(line : &str) -> Option<(String, String)> {
-	let re = Regex::new(r"^([^ ]+) ([^ ]+) .+$").unwrap();
+/// Drops everything beneath the rows of `blocktype`. In the inverse tree
+/// these are the devices that each one is built on.
+fn exclude_slaves(rows : &mut [Row], blocktype : BlockType) {
+	for row in rows {
+		if row.row_type == blocktype {
+			row.children.clear();
+		} else {
+			exclude_slaves(&mut row.children, blocktype);
+		}
+	}
+}
 
-	re.captures(line).map(|caps| {
-		(caps.at(1).unwrap().to_owned(), caps.at(2).unwrap().to_owned())
-	})
+#[test]
+fn test_exclude_slaves() {
+	let mut block = test_block();
+	block.partitions[1].holders = vec!["dm-0".to_owned()];
+	let mut lvm = test_dm_block("dm-0", 0, "vg-root", &["sda2"]);
+	lvm.metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_fs_type: Some("crypto_LUKS".to_owned()),
+		..BlockMetadata::default()
+	});
+	lvm.holders = vec!["dm-1".to_owned()];
+	let blocks = [block, lvm, test_dm_block("dm-1", 1, "luks-root", &["dm-0"])];
+	let chain = |row : &Row| {
+		let mut names = vec![row.name.to_owned()];
+		let mut row = row;
+		while let Some(child) = row.children.first() {
+			names.push(child.name.to_owned());
+			row = child;
+		}
+		names.join(">")
+	};
+
+	let rows = build_inverse_tree(block_rows(&blocks, false));
+	assert!(rows.iter().map(chain).collect::<Vec<_>>() == ["sda1>sda", "luks-root>vg-root>sda2>sda"]);
+
+	let mut rows = build_inverse_tree(block_rows(&blocks, false));
+	exclude_slaves(&mut rows, BlockType::DeviceMapper);
+	assert!(rows.iter().map(chain).collect::<Vec<_>>() == ["sda1>sda", "luks-root>vg-root"]);
+
+	let mut rows = build_inverse_tree(block_rows(&blocks, false));
+	exclude_slaves(&mut rows, BlockType::Crypt);
+	assert!(rows.iter().map(chain).collect::<Vec<_>>() == ["sda1>sda", "luks-root"]);
+}
+
+/// Removes each row, with everything beneath it, whose `column` has the
+/// same value as a row earlier in the output. Rows where the column is
+/// empty are always kept.
+fn dedup_rows(rows : &mut Vec<Row>, column : Column) {
+	dedup_subtrees(rows, column, &CellFormat { bytes: true, ..CellFormat::default() }, &mut HashSet::new());
+}
+
+fn dedup_subtrees(rows : &mut Vec<Row>, column : Column, format : &CellFormat, seen : &mut HashSet<String>) {
+	rows.retain_mut(|row| {
+		let value = column.text(row, format);
+		if !value.is_empty() && !seen.insert(value) {
+			return false;
+		}
+		dedup_subtrees(&mut row.children, column, format, seen);
+		true
+	});
 }
 
-fn parse_proc_mounts() -> Option<HashMap<String, String>> {
-	let mut file = none!(File::open("/proc/mounts"));
-	let contents = &mut String::new();
-	let _ = none!(file.read_to_string(contents));
+#[test]
+fn test_dedup_rows() {
+	let blocks = [
+		test_block(),
+		test_dm_block("dm-0", 0, "mirror", &["sda1", "sda2"]),
+	];
+	let children = |rows : &[Row]| rows[0].children.iter().map(|part| part.children.len()).collect::<Vec<_>>();
 
-	let mounts =
-		contents.lines().filter_map(parse_proc_mounts_line).collect();
+	let mut rows = build_tree(block_rows(&blocks, false));
+	dedup_rows(&mut rows, Column::Name);
+	assert!(children(&rows) == [1, 0]);
+	assert!(rows[0].children[0].children[0].name == "mirror");
 
-	Some(mounts)
+	let mut rows = build_tree(block_rows(&blocks, false));
+	dedup_rows(&mut rows, Column::FsType);
+	assert!(children(&rows) == [1, 1]);
 }
 
-fn parse_proc_swaps_line(line : &str) -> Option<String> {
-	let re = Regex::new(r"^(/[^ ]+) +.+$").unwrap();
+/// Moves each top-level row up to follow the last earlier one with the same
+/// value in `column`, so that rows sharing a value are next to each other.
+/// The groups keep the order of their first rows, and the rows keep their
+/// order within a group.
+fn group_rows(rows : &mut Vec<Row>, column : Column) {
+	let format = CellFormat { bytes: true, ..CellFormat::default() };
+	let mut groups : Vec<(String, Vec<Row>)> = Vec::new();
+	for row in rows.drain(..) {
+		let value = column.text(&row, &format);
+		match groups.iter_mut().find(|(group, _)| *group == value) {
+			Some((_, group)) => group.push(row),
+			None => groups.push((value, vec![row])),
+		}
+	}
+	rows.extend(groups.into_iter().flat_map(|(_, group)| group));
+}
 
-	re.captures(line).map(|caps| {
-		caps.at(1).unwrap().to_owned()
-	})
+#[test]
+fn test_group_rows() {
+	let disk = |name : &str, wwn : Option<&str>| {
+		let mut block = test_block();
+		block.name = name.to_owned();
+		block.partitions.clear();
+		block.metadata = Some(BlockMetadata {
+			id_type: "disk".to_owned(),
+			id_wwn: wwn.map(|wwn| wwn.to_owned()),
+			..BlockMetadata::default()
+		});
+		block
+	};
+	let blocks = [
+		disk("sda", Some("0x5000c500a1b2c3d4")),
+		disk("sdb", Some("0x600508b1001c7e4f")),
+		disk("sdc", None),
+		disk("sdd", Some("0x5000c500a1b2c3d4")),
+		disk("sde", Some("0x600508b1001c7e4f")),
+		disk("sdf", None),
+	];
+	let mut rows = block_rows(&blocks, false);
+	group_rows(&mut rows, Column::Wwn);
+	let names = rows.iter().map(|row| row.name.as_str()).collect::<Vec<_>>();
+	assert!(names == ["sda", "sdd", "sdb", "sde", "sdc", "sdf"]);
 }
 
-fn parse_proc_swaps() -> Option<HashSet<String>> {
-	let mut file = none!(File::open("/proc/swaps"));
-	let contents = &mut String::new();
-	let _ = none!(file.read_to_string(contents));
+/// Drops the partition rows so that only whole disks are printed.
+fn remove_dependents(rows : &mut [Row]) {
+	for row in rows {
+		row.children.clear();
+	}
+}
 
-        let swaps =
-		contents.lines().filter_map(parse_proc_swaps_line).collect();
+trait Formatter {
+	fn write_rows(&self, out : &mut dyn Write, rows : &[Row]) -> io::Result<()>;
+}
 
-	Some(swaps)
+/// The strings used to draw the tree in the NAME column.
+struct TreeChars {
+	branch : &'static str,
+	last : &'static str,
+	vertical : &'static str,
+	space : &'static str,
+	/// Marks where a cell was cut short to fit the terminal.
+	ellipsis : &'static str,
 }
 
-fn read_partition_mountpoint(name : &str) -> String {
-	let path = format!("/dev/{}", name);
-	let mounts = parse_proc_mounts().unwrap();
-	match mounts.get(&path) {
-		Some(mount) => mount.to_owned(),
-		None => {
-			let swaps = parse_proc_swaps().unwrap();
-			String::from(if swaps.contains(&path) {
-				"[SWAP]"
-			} else {
-				""
-			})
+const UNICODE_TREE : TreeChars = TreeChars {
+	branch: "\u{251C}\u{2500}",
+	last: "\u{2514}\u{2500}",
+	vertical: "\u{2502} ",
+	space: "  ",
+	ellipsis: "\u{2026}",
+};
+
+const ASCII_TREE : TreeChars = TreeChars {
+	branch: "|-",
+	last: "`-",
+	vertical: "| ",
+	space: "  ",
+	ellipsis: "...",
+};
+
+/// Whether a locale name such as `en_US.UTF-8` uses the UTF-8 encoding.
+fn is_utf8_locale(locale : &str) -> bool {
+	let locale = locale.to_ascii_lowercase();
+	locale.contains("utf-8") || locale.contains("utf8")
+}
+
+#[test]
+fn test_is_utf8_locale() {
+	assert!(is_utf8_locale("en_US.UTF-8"));
+	assert!(is_utf8_locale("de_DE.utf8"));
+	assert!(is_utf8_locale("C.UTF-8"));
+	assert!(!is_utf8_locale("C"));
+	assert!(!is_utf8_locale("POSIX"));
+	assert!(!is_utf8_locale("en_US.ISO-8859-1"));
+	assert!(!is_utf8_locale(""));
+}
+
+/// The character type locale, following the same precedence as setlocale.
+fn ctype_locale() -> String {
+	["LC_ALL", "LC_CTYPE", "LANG"].iter()
+		.filter_map(|var| env::var(var).ok())
+		.find(|value| !value.is_empty())
+		.unwrap_or_default()
+}
+
+fn stdout_is_tty() -> bool {
+	unsafe { libc::isatty(libc::STDOUT_FILENO) == 1 }
+}
+
+/// The number of columns in the terminal on stdout, if it is one.
+fn terminal_width() -> Option<usize> {
+	let mut size : libc::winsize = unsafe { mem::zeroed() };
+	match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+		0 if size.ws_col > 0 => Some(size.ws_col as usize),
+		_ => None,
+	}
+}
+
+/// Cuts `text` down to at most `width` terminal cells, ending it with
+/// `ellipsis` when anything was removed.
+fn elide(text : &str, width : usize, ellipsis : &str) -> String {
+	if text.width() <= width {
+		return text.to_owned();
+	}
+	let ellipsis = if ellipsis.width() < width { ellipsis } else { "" };
+	let room = width - ellipsis.width();
+	let mut elided = String::new();
+	let mut used = 0;
+	for c in text.chars() {
+		let char_width = c.width().unwrap_or(0);
+		if used + char_width > room {
+			break;
 		}
+		used += char_width;
+		elided.push(c);
 	}
+	elided.push_str(ellipsis);
+	elided
 }
 
-fn read_partitions(path : &Path, block_name : &str) -> Vec<Partition> {
-	let mut ps = Vec::new();
-	let entries = fs::read_dir(path).unwrap();
-	for entry in entries {
-		let entry = entry.unwrap();
-		let entry_path = entry.path();
-		let entry_path = entry_path.as_path();
-		let entry_name = entry.file_name();
-		let entry_name = entry_name.to_string_lossy().into_owned();
-		if entry_name.starts_with(block_name) {
-			let removable = parse_block_file(entry_path, "removable");
-			let majmin = parse_block_file(entry_path, "dev");
+#[test]
+fn test_elide() {
+	assert!(elide("/boot", 5, "\u{2026}") == "/boot");
+	assert!(elide("/boot/efi", 5, "\u{2026}") == "/boo\u{2026}");
+	assert!(elide("/boot/efi", 5, "...") == "/b...");
+	assert!(elide("/mnt/\u{65E5}\u{672C}", 7, "\u{2026}") == "/mnt/\u{2026}");
+	assert!(elide("/boot", 2, "...") == "/b");
+}
+
+/// The pager command line, split into words, from the value of `$PAGER`.
+/// An unset `$PAGER` means `less`, and an empty one turns paging off.
+fn pager_words(pager : Option<String>) -> Option<Vec<String>> {
+	let pager = pager.unwrap_or_else(|| "less".to_owned());
+	let words : Vec<String> = pager.split_whitespace().map(|word| word.to_owned()).collect();
+	if words.is_empty() {
+		None
+	} else {
+		Some(words)
+	}
+}
+
+#[test]
+fn test_pager_words() {
+	assert!(pager_words(None) == Some(vec!["less".to_owned()]));
+	assert!(pager_words(Some("more".to_owned())) == Some(vec!["more".to_owned()]));
+	assert!(pager_words(Some("less -S ".to_owned())) == Some(vec!["less".to_owned(), "-S".to_owned()]));
+	assert!(pager_words(Some("".to_owned())).is_none());
+	assert!(pager_words(Some("  ".to_owned())).is_none());
+}
+
+/// Starts the pager with a pipe to its stdin, or returns `None` when there is
+/// no pager or it cannot be run. Like git, `less` is told to quit when the
+/// output fits on one screen and to pass colors through, unless `$LESS` says
+/// otherwise.
+fn spawn_pager() -> Option<process::Child> {
+	let words = pager_words(env::var("PAGER").ok())?;
+	Command::new(&words[0])
+		.args(&words[1..])
+		.env("LESS", env::var("LESS").unwrap_or_else(|_| "FRX".to_owned()))
+		.stdin(Stdio::piped())
+		.spawn()
+		.ok()
+}
+
+const BOLD : &str = "\x1b[1m";
+const RED : &str = "\x1b[31m";
+const GREEN : &str = "\x1b[32m";
+const RESET : &str = "\x1b[0m";
 
-			if majmin.is_none() {
-				continue
+struct TreeFormatter {
+	columns : Vec<Column>,
+	cells : CellFormat,
+	tree : &'static TreeChars,
+	headings : bool,
+	/// The column that the tree is drawn in, usually NAME. Without one the
+	/// devices are printed as a flat list.
+	tree_column : Option<Column>,
+	/// Print a blank line between top-level devices whose value in this
+	/// column differs, to separate the groups made by `--group-by`.
+	group_separator : Option<Column>,
+	/// Indent the partition of a disk that has only one, rather than
+	/// drawing a branch to it.
+	compact_single_partition : bool,
+	/// Highlight cells with ANSI escapes.
+	color : bool,
+	/// Written after the header and after each device.
+	terminator : &'static str,
+	/// The width to fit each line into by eliding the last column.
+	width : Option<usize>,
+}
+
+impl TreeFormatter {
+	fn new(columns : Vec<Column>) -> TreeFormatter {
+		TreeFormatter {
+			columns,
+			cells: CellFormat::default(),
+			tree: &UNICODE_TREE,
+			headings: true,
+			tree_column: Some(Column::Name),
+			group_separator: None,
+			compact_single_partition: false,
+			color: false,
+			terminator: "\n",
+			width: None,
+		}
+	}
+
+	/// The escape sequence that highlights `column` for `row`, if any.
+	fn style(&self, column : Column, row : &Row) -> Option<&'static str> {
+		if !self.color {
+			return None;
+		}
+		match column {
+			Column::Name if row.readonly.is_some_and(|readonly| readonly != 0) => Some(RED),
+			Column::Name if matches!(row.row_type, BlockType::Disk) => Some(BOLD),
+			Column::Mountpoint | Column::Mountpoints => Some(GREEN),
+			Column::Alignment if row.alignment_offset.is_some_and(|offset| offset != 0) => Some(RED),
+			_ => None,
+		}
+	}
+
+	/// Lays out one line of cells. The padding is worked out from the plain
+	/// text, and any highlighting is wrapped around it afterwards, so that
+	/// escape sequences do not count towards the column widths.
+	fn line(&self, cells : &[String], row : Option<&Row>, widths : &[usize]) -> String {
+		let mut line = String::new();
+		for (i, (cell, &column)) in cells.iter().zip(&self.columns).enumerate() {
+			if i > 0 {
+				line.push(' ');
+			}
+			let padding = " ".repeat(widths[i] - cell.width());
+			// The branches drawn in front of the tree column stay plain.
+			let (prefix, text) = if Some(column) == self.tree_column { self.split_tree_prefix(cell) } else { ("", &cell[..]) };
+			let cell = match row.and_then(|row| self.style(column, row)) {
+				Some(style) if !text.trim().is_empty() => format!("{}{}{}{}", prefix, style, text, RESET),
+				_ => cell.to_owned(),
+			};
+			match column.align() {
+				Align::Right => {
+					line.push_str(&padding);
+					line.push_str(&cell);
+				},
+				Align::Left => {
+					line.push_str(&cell);
+					if i+1 < cells.len() {
+						line.push_str(&padding);
+					}
+				},
 			}
+		}
+		line
+	}
 
-			let majmin = majmin.unwrap();
+	/// Splits a cell of the tree column into the branches drawn in front of
+	/// it and the value itself.
+	fn split_tree_prefix<'a>(&self, cell : &'a str) -> (&'a str, &'a str) {
+		let tree = [self.tree.branch, self.tree.last, self.tree.vertical, self.tree.space];
+		let start = cell.find(|c : char| !tree.iter().any(|part| part.contains(c))).unwrap_or(cell.len());
+		cell.split_at(start)
+	}
+
+	/// The cells for `row`, with `prefix` drawn in front of the tree column.
+	fn cells(&self, prefix : &str, row : &Row) -> Vec<String> {
+		self.columns.iter().map(|&column| {
+			let text = column.text(row, &self.cells);
+			if Some(column) == self.tree_column {
+				format!("{}{}", prefix, text)
+			} else {
+				text
+			}
+		}).collect()
+	}
 
-			let size = parse_sector_file(entry_path, "size");
-			let readonly = parse_block_file(entry_path, "ro");
-			let meta = load_uevent_metadata(&majmin);
-			let mountpoint = read_partition_mountpoint(&entry_name);
-			ps.push(Partition { name: entry_name, removable: removable, majmin: majmin, size: size, readonly: readonly, metadata: meta, mountpoint: mountpoint })
+	fn child_cells<'a>(&self, lines : &mut Vec<(Option<&'a Row>, Vec<String>)>, indent : &str, row : &'a Row) {
+		let compact = self.compact_single_partition && matches!(row.row_type, BlockType::Disk) &&
+			row.children.len() == 1 && matches!(row.children[0].row_type, BlockType::Partition);
+		for (i, child) in row.children.iter().enumerate() {
+			let last = i+1 == row.children.len();
+			let branch = if compact { self.tree.space } else if last { self.tree.last } else { self.tree.branch };
+			lines.push((Some(child), self.cells(&format!("{}{}", indent, branch), child)));
+			let continuation = if last { self.tree.space } else { self.tree.vertical };
+			self.child_cells(lines, &format!("{}{}", indent, continuation), child);
+		}
+		if row.truncated {
+			// Marks where `--tree-depth` cut the tree short, in the place
+			// of the children that are not shown.
+			let marker = self.columns.iter().map(|&column| {
+				if Some(column) == self.tree_column {
+					format!("{}{}{}", indent, self.tree.last, self.tree.ellipsis)
+				} else {
+					String::new()
+				}
+			}).collect();
+			lines.push((None, marker));
 		}
 	}
-	ps
 }
 
-fn read_block(dir : DirEntry) -> Option<Block> {
-	let path = dir.path();
-	let path = path.as_path();
-	let name = dir.file_name();
-	let name = name.to_string_lossy().into_owned();
-	let majmin : Option<MajorMinor> = parse_block_file(path, "dev");
-	match majmin {
-		Some(majmin) => {
-			let removable = parse_block_file(path, "removable");
-			let size = parse_sector_file(path, "size");
-			let readonly = parse_block_file(path, "ro");
-			let parts = read_partitions(path, &name);
-			let mountpoint = String::from("");
-			Some(Block { name: name, removable: removable, majmin: majmin, size: size, readonly: readonly, partitions: parts, mountpoint: mountpoint })
-		},
-		_ => None,
+impl Formatter for TreeFormatter {
+	fn write_rows(&self, out : &mut dyn Write, rows : &[Row]) -> io::Result<()> {
+		let mut lines = Vec::new();
+		for (i, row) in rows.iter().enumerate() {
+			// A separator is a line with no cells at all, so that it is
+			// printed empty rather than padded out to the column widths.
+			if let Some(column) = self.group_separator {
+				if i > 0 && column.text(&rows[i-1], &self.cells) != column.text(row, &self.cells) {
+					lines.push((None, Vec::new()));
+				}
+			}
+			if self.tree_column.is_none() {
+				let mut flat = Vec::new();
+				flatten_rows(slice::from_ref(row), &mut flat);
+				lines.extend(flat.into_iter().map(|row| (Some(row), self.cells("", row))));
+			} else {
+				lines.push((Some(row), self.cells("", row)));
+				self.child_cells(&mut lines, "", row);
+			}
+		}
+
+		let header = self.columns.iter().map(|column| column.header().to_owned()).collect::<Vec<_>>();
+		// Widths are measured in terminal cells rather than characters, as
+		// labels can hold wide CJK characters or combining marks.
+		let mut widths = (0..self.columns.len()).map(|i| {
+			lines.iter().map(|(_, cells)| cells).chain(Some(&header)).filter_map(|cells| cells.get(i)).flat_map(|cell| cell.lines()).map(|line| line.width()).max().unwrap_or(0)
+		}).collect::<Vec<_>>();
+
+		// Only the last column gives way to a narrow terminal, and never
+		// below the width of its heading.
+		if let (Some(width), Some(last)) = (self.width, widths.len().checked_sub(1)) {
+			let used = widths[..last].iter().sum::<usize>() + last;
+			widths[last] = widths[last].min(width.saturating_sub(used).max(header[last].width()));
+		}
+
+		// The header still counts towards the widths without headings, so
+		// that the rows are laid out exactly as they would be with them.
+		if self.headings {
+			write!(out, "{}{}", self.line(&header, None, &widths), self.terminator)?;
+		}
+		for (row, cells) in lines {
+			// A multi-line cell, such as MOUNTPOINTS, continues onto extra
+			// lines with the other columns left blank. These stay part of the
+			// device's record when records are NUL-terminated.
+			let height = cells.iter().map(|cell| cell.lines().count()).max().unwrap_or(0).max(1);
+			let record = (0..height).map(|n| {
+				let line = cells.iter().zip(&widths).map(|(cell, &width)| {
+					elide(cell.lines().nth(n).unwrap_or(""), width, self.tree.ellipsis)
+				}).collect::<Vec<_>>();
+				self.line(&line, row, &widths)
+			}).collect::<Vec<_>>();
+			write!(out, "{}{}", record.join("\n"), self.terminator)?;
+		}
+		Ok(())
 	}
 }
 
-#[derive(Debug)]
-#[derive(PartialEq)]
-struct KeyValue<'a> {
-	key : &'a str,
-	value : &'a str,
+/// Visits every row in tree order, parents before their children.
+fn flatten_rows<'a>(rows : &'a [Row], flat : &mut Vec<&'a Row>) {
+	for row in rows {
+		flat.push(row);
+		flatten_rows(&row.children, flat);
+	}
 }
 
-fn parse_line(line : &str) -> Option<KeyValue> {
-	let re = Regex::new(r"^E:([^=]+)=([^=]+)$").unwrap();
-
-	re.captures(line).map(|caps| {
-		KeyValue { key : caps.at(1).unwrap(), value : caps.at(2).unwrap() }
-	})
+/// Quotes a value for `--pairs` output. Quotes, backslashes and control
+/// characters are written as `\xNN` escapes, so every record stays on one
+/// line and can be split on spaces outside of quotes.
+fn quote_pair_value(value : &str) -> String {
+	let mut quoted = String::with_capacity(value.len() + 2);
+	quoted.push('"');
+	for c in value.chars() {
+		match c {
+			'"' | '\\' => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+			c if c.is_control() && (c as u32) < 0x80 => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+			c => quoted.push(c),
+		}
+	}
+	quoted.push('"');
+	quoted
 }
 
 #[test]
-fn test_parse_line() {
-	assert!(parse_line("E:ID_ATA_FEATURE_SET_PM=1") ==
-		Some(KeyValue { key:"ID_ATA_FEATURE_SET_PM", value: "1"}));
+fn test_quote_pair_value() {
+	assert!(quote_pair_value("sda") == "\"sda\"");
+	assert!(quote_pair_value("") == "\"\"");
+	assert!(quote_pair_value("/mnt/my disk") == "\"/mnt/my disk\"");
+	assert!(quote_pair_value("a\"b\\c") == "\"a\\x22b\\x5cc\"");
+	assert!(quote_pair_value("/\n/home") == "\"/\\x0a/home\"");
+}
 
-	assert!(parse_line("E:KEY=one two three") ==
-		Some(KeyValue { key:"KEY", value: "one two three"}));
+/// Prints each device as a line of `KEY="value"` pairs, as `lsblk -P` does.
+/// Every column is written for every device, as `KEY=""` when it has no
+/// value, so that each line has the same keys in the same order.
+struct PairsFormatter {
+	columns : Vec<Column>,
+	cells : CellFormat,
+	/// Written after each device.
+	terminator : &'static str,
+}
 
-	assert!(parse_line("W:12") == None);
-	assert!(parse_line("E:ID_ATA_FEATURE_SET_PM") == None);
-	assert!(parse_line("E:ID_ATA_FEATURE_SET_PM=1=1") == None);
+impl Formatter for PairsFormatter {
+	fn write_rows(&self, out : &mut dyn Write, rows : &[Row]) -> io::Result<()> {
+		let mut flat = Vec::new();
+		flatten_rows(rows, &mut flat);
+		for row in flat {
+			let pairs = self.columns.iter().map(|column| {
+				let text = column.text(row, &self.cells);
+				format!("{}={}", column.header(), quote_pair_value(text.trim()))
+			}).collect::<Vec<_>>();
+			write!(out, "{}{}", pairs.join(" "), self.terminator)?;
+		}
+		Ok(())
+	}
 }
 
-fn parse_uevent_metadata(data : &str) -> Option<BlockMetadata> {
-	let mut id_type = None;
-	let mut id_fs_type = None;
-	let mut id_fs_uuid = None;
+struct JsonFormatter {
+	columns : Vec<Column>,
+	/// List every device in one array, each with the name of its `parent`,
+	/// rather than nesting them under `children`.
+	flat : bool,
+}
 
-	for kv in data.lines().map(parse_line) {
-		match kv {
-			Some(KeyValue { key:"ID_TYPE", value }) => {
-				id_type = Some(value.to_owned())
-			},
-			Some(KeyValue { key:"ID_FS_TYPE", value }) => {
-				id_fs_type = Some(value.to_owned())
+impl JsonFormatter {
+	fn fields(&self, row : &Row) -> Vec<(&'static str, Json)> {
+		self.columns.iter().map(|column| (column.json_key(), column.json(row))).collect()
+	}
+
+	fn row_json(&self, row : &Row) -> Json {
+		let mut fields = self.fields(row);
+		if !row.children.is_empty() {
+			fields.push(("children", Json::Array(row.children.iter().map(|child| self.row_json(child)).collect())));
+		}
+		Json::Object(fields)
+	}
+
+	/// Adds `rows` and everything beneath them to `devices` in tree order.
+	/// A device built on several others is listed once under each.
+	fn flat_json(&self, rows : &[Row], parent : Option<&Row>, devices : &mut Vec<Json>) {
+		for row in rows {
+			let mut fields = self.fields(row);
+			fields.push(("parent", parent.map_or(Json::Null, |parent| Json::text(&parent.name))));
+			devices.push(Json::Object(fields));
+			self.flat_json(&row.children, Some(row), devices);
+		}
+	}
+}
+
+impl Formatter for JsonFormatter {
+	fn write_rows(&self, out : &mut dyn Write, rows : &[Row]) -> io::Result<()> {
+		let devices = if self.flat {
+			let mut devices = Vec::new();
+			self.flat_json(rows, None, &mut devices);
+			devices
+		} else {
+			rows.iter().map(|row| self.row_json(row)).collect()
+		};
+		Json::Object(vec![("blockdevices", Json::Array(devices))]).write_pretty(out)
+	}
+}
+
+/// Writes each top-level device, with its children nested as in `-J`, as a
+/// compact JSON object on a line of its own.
+struct JsonLinesFormatter {
+	json : JsonFormatter,
+}
+
+impl Formatter for JsonLinesFormatter {
+	fn write_rows(&self, out : &mut dyn Write, rows : &[Row]) -> io::Result<()> {
+		for row in rows {
+			self.json.row_json(row).write_compact(out)?;
+			writeln!(out)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+fn test_block() -> Block {
+	Block {
+		name: "sda".to_owned(),
+		majmin: MajorMinor { major: 8, minor: 0 },
+		removable: Some(0),
+		hotplug: Some(0),
+		size: Some(34359738368),
+		readonly: Some(0),
+		rotational: Some(1),
+		logical_block_size: Some(512),
+		physical_block_size: Some(4096),
+		alignment_offset: Some(0),
+		minimum_io_size: Some(4096),
+		optimal_io_size: Some(0),
+		discard_granularity: Some(0),
+		discard_max_bytes: Some(0),
+		dax: Some(0),
+		scheduler: Some("mq-deadline".to_owned()),
+		transport: Some("sata".to_owned()),
+		state: Some("running".to_owned()),
+		partitions: vec![
+			Partition {
+				name: "sda1".to_owned(),
+				majmin: MajorMinor { major: 8, minor: 1 },
+				removable: None,
+				size: Some(60063744),
+				start: Some(2048),
+				alignment_offset: Some(0),
+				readonly: Some(0),
+				holders: Vec::new(),
+				node: None,
+				dev_path: None,
+				metadata: Some(BlockMetadata {
+					id_type: "disk".to_owned(),
+					id_fs_type: Some("vfat".to_owned()),
+					..BlockMetadata::default()
+				}),
+				mountpoint: "/boot".to_owned(),
+				mountpoints: vec!["/boot".to_owned()],
+				mount_options: "rw,relatime".to_owned(),
 			},
-			Some(KeyValue { key:"ID_FS_UUID", value }) => {
-				id_fs_uuid = Some(value.to_owned())
+			Partition {
+				name: "sda2".to_owned(),
+				majmin: MajorMinor { major: 8, minor: 2 },
+				removable: None,
+				size: Some(34299674624),
+				start: Some(119360),
+				alignment_offset: Some(0),
+				readonly: Some(0),
+				holders: Vec::new(),
+				node: None,
+				dev_path: None,
+				metadata: None,
+				mountpoint: "/".to_owned(),
+				mountpoints: vec!["/".to_owned(), "/home".to_owned(), "/var/lib/docker".to_owned()],
+				mount_options: "rw,noatime,errors=remount-ro".to_owned(),
 			},
-			_ => {}
-		}
+		],
+		dm_name: None,
+		raid_level: None,
+		backing_file: None,
+		zram: None,
+		slaves: Vec::new(),
+		holders: Vec::new(),
+		node: None,
+		dev_path: None,
+		metadata: None,
+		mountpoint: String::new(),
+		mountpoints: Vec::new(),
+		mount_options: String::new(),
 	}
+}
 
-	match (id_type, id_fs_type, id_fs_uuid) {
-		(Some(id_type), id_fs_type, id_fs_uuid) =>
-			Some(BlockMetadata {
-				id_type : id_type,
-				id_fs_type : id_fs_type,
-				id_fs_uuid : id_fs_uuid,
-			}),
-		_ => None
+#[cfg(test)]
+fn test_dm_block(name : &str, minor : u32, dm_name : &str, slaves : &[&str]) -> Block {
+	Block {
+		name: name.to_owned(),
+		majmin: MajorMinor { major: 253, minor },
+		removable: Some(0),
+		hotplug: Some(0),
+		size: Some(10737418240),
+		readonly: Some(0),
+		rotational: Some(0),
+		logical_block_size: Some(512),
+		physical_block_size: Some(512),
+		alignment_offset: Some(0),
+		minimum_io_size: Some(65536),
+		optimal_io_size: Some(131072),
+		discard_granularity: Some(4096),
+		discard_max_bytes: Some(2147450880),
+		dax: None,
+		scheduler: None,
+		transport: None,
+		state: None,
+		partitions: Vec::new(),
+		dm_name: Some(dm_name.to_owned()),
+		raid_level: None,
+		backing_file: None,
+		zram: None,
+		slaves: slaves.iter().map(|slave| slave.to_string()).collect(),
+		holders: Vec::new(),
+		node: None,
+		dev_path: None,
+		metadata: None,
+		mountpoint: String::new(),
+		mountpoints: Vec::new(),
+		mount_options: String::new(),
 	}
 }
 
+#[cfg(test)]
+fn format_rows(formatter : &dyn Formatter, blocks : &[Block]) -> String {
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &block_rows(blocks, false)).unwrap();
+	String::from_utf8(out).unwrap()
+}
+
 #[test]
-fn test_parse_uevent_metadata() {
-	assert!(
-		parse_uevent_metadata("E:ID_TYPE=disk") ==
-		Some(BlockMetadata {
-			id_type: "disk".to_string(),
-			id_fs_type: None,
-			id_fs_uuid: None,
-		})
-	);
-
-	assert!(
-		parse_uevent_metadata("E:ID_TYPE=disk\nE:ID_FS_TYPE=ext4") ==
-		Some(BlockMetadata {
-			id_type: "disk".to_string(),
-			id_fs_type: Some("ext4".to_string()),
-			id_fs_uuid: None,
-		})
-	);
-
-	assert!(
-		parse_uevent_metadata("E:ID_FS_TYPE=ext4") == None
-	);
-
-	assert!(
-		parse_uevent_metadata("E:ID_TYPE=disk\nE:ID_FS_UUID=eca1e7f9-42c7-49b7-9f42-bec0c3e975e6") ==
-		Some(BlockMetadata {
-			id_type: "disk".to_string(),
-			id_fs_type: None,
-			id_fs_uuid: Some("eca1e7f9-42c7-49b7-9f42-bec0c3e975e6".to_string()),
-		})
-	);
+fn test_tree_formatter() {
+	let output = format_rows(&TreeFormatter::new(column::DEFAULT_COLUMNS.to_vec()), &[test_block()]);
+	assert!(output ==
+		"NAME   MAJ:MIN RM  SIZE RO FSTYPE TYPE MOUNTPOINT\n\
+		 sda      8:0    0   32G  0        disk \n\
+		 \u{251C}\u{2500}sda1   8:1    0   57M  0 vfat   part /boot\n\
+		 \u{2514}\u{2500}sda2   8:2    0   32G  0        part /\n");
+}
+
+#[test]
+fn test_tree_formatter_nested() {
+	let blocks = [
+		test_block(),
+		test_dm_block("dm-1", 1, "root-crypt", &["dm-0"]),
+		test_dm_block("dm-0", 0, "vg-root", &["sda2"]),
+		test_dm_block("dm-2", 2, "orphan", &["sdz"]),
+	];
+	let formatter = TreeFormatter::new(vec![Column::Name, Column::Type, Column::Size]);
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &build_tree(block_rows(&blocks, false))).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME             TYPE  SIZE\n\
+		 sda              disk   32G\n\
+		 \u{251C}\u{2500}sda1           part   57M\n\
+		 \u{2514}\u{2500}sda2           part   32G\n\
+		 \u{0020} \u{2514}\u{2500}vg-root      dm     10G\n\
+		 \u{0020}   \u{2514}\u{2500}root-crypt dm     10G\n\
+		 orphan           dm     10G\n");
 }
 
-fn load_uevent_metadata(device : &MajorMinor) -> Option<BlockMetadata> {
-	let path = device.udev_path();
-	let mut file = none!(File::open(path));
-	let contents = &mut String::new();
-	let _ = none!(file.read_to_string(contents));
-	parse_uevent_metadata(contents)
+#[test]
+fn test_tree_formatter_ascii() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "boot-crypt", &["sda1"])];
+	let formatter = TreeFormatter {
+		tree: &ASCII_TREE,
+		..TreeFormatter::new(vec![Column::Name, Column::Type])
+	};
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &build_tree(block_rows(&blocks, false))).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME           TYPE\n\
+		 sda            disk\n\
+		 |-sda1         part\n\
+		 | `-boot-crypt dm\n\
+		 `-sda2         part\n");
 }
 
-enum BlockType { Disk, Partition }
+#[test]
+fn test_tree_formatter_luks() {
+	let mut block = test_block();
+	block.partitions[1].metadata = Some(BlockMetadata {
+		id_type: "partition".to_owned(),
+		id_fs_type: Some("crypto_LUKS".to_owned()),
+		..BlockMetadata::default()
+	});
+	block.partitions[1].holders = vec!["dm-0".to_owned()];
+	let mut crypt = test_dm_block("dm-0", 0, "luks-root", &["sda2"]);
+	crypt.metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_fs_type: Some("ext4".to_owned()),
+		..BlockMetadata::default()
+	});
+	let blocks = [block, crypt, test_dm_block("dm-1", 1, "vg-swap", &[])];
+	let formatter = TreeFormatter::new(vec![Column::Name, Column::FsType, Column::Type]);
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &build_tree(block_rows(&blocks, false))).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME          FSTYPE      TYPE\n\
+		 sda                       disk\n\
+		 \u{251C}\u{2500}sda1        vfat        part\n\
+		 \u{2514}\u{2500}sda2        crypto_LUKS part\n\
+		 \u{0020} \u{2514}\u{2500}luks-root ext4        crypt\n\
+		 vg-swap                   dm\n");
+}
 
-fn describe_block_type(blocktype : BlockType) -> &'static str {
-	match blocktype {
-		BlockType::Disk => "disk",
-		BlockType::Partition => "part",
+#[test]
+fn test_tree_formatter_raid() {
+	let raid_member = Some(BlockMetadata {
+		id_type: "partition".to_owned(),
+		id_fs_type: Some("linux_raid_member".to_owned()),
+		..BlockMetadata::default()
+	});
+	let mut block = test_block();
+	block.partitions[0].metadata = raid_member.clone();
+	block.partitions[0].holders = vec!["md0".to_owned()];
+	block.partitions[1].metadata = raid_member;
+	block.partitions[1].holders = vec!["md0".to_owned()];
+	let md = Block {
+		raid_level: Some("raid1".to_owned()),
+		dm_name: None,
+		..test_dm_block("md0", 0, "", &["sda1", "sda2"])
+	};
+	let formatter = TreeFormatter::new(vec![Column::Name, Column::Type, Column::Raid]);
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &build_tree(block_rows(&[block.clone(), md], false))).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME    TYPE RAID\n\
+		 sda     disk \n\
+		 \u{251C}\u{2500}sda1  part \n\
+		 \u{2502} \u{2514}\u{2500}md0 md   raid1\n\
+		 \u{2514}\u{2500}sda2  part \n\
+		 \u{0020} \u{2514}\u{2500}md0 md   raid1\n");
+
+	// The members of an array that is not assembled have no md device.
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &build_tree(block_rows(&[block], false))).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME   TYPE RAID\n\
+		 sda    disk \n\
+		 \u{251C}\u{2500}sda1 part \n\
+		 \u{2514}\u{2500}sda2 part \n");
+}
+
+#[test]
+fn test_build_tree_multiple() {
+	let blocks = [
+		test_block(),
+		test_dm_block("dm-0", 0, "mirror", &["sda1", "sda2"]),
+	];
+	let rows = build_tree(block_rows(&blocks, false));
+	assert!(rows.len() == 1);
+	assert!(rows[0].children.iter().map(|part| part.children.iter().map(|row| row.name.to_owned()).collect::<Vec<_>>()).collect::<Vec<_>>() ==
+		[["mirror"], ["mirror"]]);
+}
+
+#[test]
+fn test_build_tree_holders() {
+	let mut block = test_block();
+	block.partitions[0].holders = vec!["md0".to_owned()];
+	block.partitions[1].holders = vec!["md0".to_owned()];
+	let mut md = test_dm_block("md0", 0, "md0", &[]);
+	md.dm_name = None;
+	md.holders = vec!["dm-0".to_owned()];
+	let blocks = [block, md, test_dm_block("dm-0", 0, "vg-root", &["md0"])];
+
+	let rows = build_tree(block_rows(&blocks, false));
+	assert!(rows.len() == 1);
+	for part in &rows[0].children {
+		assert!(part.children.len() == 1);
+		assert!(part.children[0].kname == "md0");
+		assert!(part.children[0].children.iter().map(|row| row.name.to_owned()).collect::<Vec<_>>() == ["vg-root"]);
 	}
 }
 
-struct Row {
-	name: String,
-	majmin: String,
-	removable: &'static str,
-	size: String,
-	readonly: &'static str,
-	row_type: BlockType,
-	mountpoint : String,
+#[test]
+fn test_build_inverse_tree() {
+	let blocks = [
+		test_block(),
+		test_dm_block("dm-0", 0, "mirror", &["sda1", "sda2"]),
+	];
+	let rows = build_inverse_tree(block_rows(&blocks, false));
+	let tree = |row : &Row| row.children.iter().map(|child| {
+		let disks = child.children.iter().map(|disk| disk.name.to_owned()).collect::<Vec<_>>();
+		format!("{}({})", child.name, disks.join(","))
+	}).collect::<Vec<_>>();
+	assert!(rows.len() == 1);
+	assert!(rows[0].name == "mirror");
+	assert!(tree(&rows[0]) == ["sda1(sda)", "sda2(sda)"]);
+
+	let rows = build_inverse_tree(block_rows(&[test_block()], false));
+	assert!(rows.iter().map(|row| row.name.to_owned()).collect::<Vec<_>>() == ["sda1", "sda2"]);
+	assert!(rows[0].children[0].name == "sda");
+}
+
+#[test]
+fn test_sort_rows() {
+	let names = |rows : &[Row]| rows.iter().map(|row| {
+		let children = row.children.iter().map(|child| child.name.to_owned()).collect::<Vec<_>>();
+		format!("{}({})", row.name, children.join(","))
+	}).collect::<Vec<_>>();
+
+	let mut small = test_block();
+	small.name = "sdb".to_owned();
+	small.majmin = MajorMinor { major: 8, minor: 16 };
+	small.size = Some(1024);
+	let mut nvme = test_block();
+	nvme.name = "nvme0n1".to_owned();
+	nvme.majmin = MajorMinor { major: 259, minor: 0 };
+	nvme.size = Some(500107862016);
+	nvme.partitions.clear();
+	let blocks = [test_block(), nvme, small];
+
+	let mut rows = block_rows(&blocks, false);
+	sort_rows(&mut rows, &[Column::Name], false);
+	assert!(names(&rows) == ["nvme0n1()", "sda(sda1,sda2)", "sdb(sda1,sda2)"]);
+
+	sort_rows(&mut rows, &[Column::Size], false);
+	assert!(names(&rows) == ["sdb(sda1,sda2)", "sda(sda1,sda2)", "nvme0n1()"]);
+
+	sort_rows(&mut rows, &[Column::MajMin], false);
+	assert!(names(&rows) == ["sda(sda1,sda2)", "sdb(sda1,sda2)", "nvme0n1()"]);
+
+	rows[0].children.reverse();
+	sort_rows(&mut rows, &[Column::MajMin], false);
+	assert!(names(&rows)[0] == "sda(sda1,sda2)");
+}
+
+#[test]
+fn test_sort_rows_keys() {
+	let names = |rows : &[Row]| rows.iter().map(|row| row.name.to_owned()).collect::<Vec<_>>();
+
+	let mut sdb = test_block();
+	sdb.name = "sdb".to_owned();
+	sdb.majmin = MajorMinor { major: 8, minor: 16 };
+	let mut sdc = test_block();
+	sdc.name = "sdc".to_owned();
+	sdc.majmin = MajorMinor { major: 8, minor: 32 };
+	sdc.size = Some(1024);
+	let mut loop0 = test_block();
+	loop0.name = "loop0".to_owned();
+	loop0.majmin = MajorMinor { major: 7, minor: 0 };
+	loop0.size = None;
+	let blocks = [sdb, loop0, test_block(), sdc];
+
+	let mut rows = block_rows(&blocks, false);
+	sort_rows(&mut rows, &[Column::Size], false);
+	assert!(names(&rows) == ["sdc", "sdb", "sda", "loop0"]);
+
+	sort_rows(&mut rows, &[Column::Size, Column::Name], false);
+	assert!(names(&rows) == ["sdc", "sda", "sdb", "loop0"]);
+
+	sort_rows(&mut rows, &[Column::Size, Column::Name], true);
+	assert!(names(&rows) == ["loop0", "sdb", "sda", "sdc"]);
+	assert!(rows[1].children.iter().map(|row| row.name.to_owned()).collect::<Vec<_>>() == ["sda2", "sda1"]);
+}
+
+#[test]
+fn test_tree_formatter_nodeps() {
+	let mut rows = block_rows(&[test_block(), test_block()], false);
+	remove_dependents(&mut rows);
+
+	let formatter = TreeFormatter::new(vec![Column::Name, Column::Size, Column::Type]);
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &rows).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME  SIZE TYPE\n\
+		 sda    32G disk\n\
+		 sda    32G disk\n");
+}
+
+#[test]
+fn test_tree_formatter_output_all() {
+	let columns = options::parse_args(vec!["-O".to_owned()].into_iter()).unwrap().columns.unwrap();
+	let output = format_rows(&TreeFormatter::new(columns), &[test_block()]);
+	let header = output.lines().next().unwrap().split_whitespace().collect::<Vec<_>>();
+	assert!(header == column::ALL_COLUMNS.iter().map(|column| column.header()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_tree_formatter_single_partition() {
+	let mut sdb = test_block();
+	sdb.name = "sdb".to_owned();
+	sdb.partitions.truncate(1);
+	sdb.partitions[0].name = "sdb1".to_owned();
+	let blocks = [test_block(), sdb];
+	let formatter = TreeFormatter { compact_single_partition: true, ..TreeFormatter::new(vec![Column::Name, Column::Type]) };
+	assert!(format_rows(&formatter, &blocks) ==
+		"NAME   TYPE\n\
+		 sda    disk\n\
+		 \u{251C}\u{2500}sda1 part\n\
+		 \u{2514}\u{2500}sda2 part\n\
+		 sdb    disk\n\
+		 \x20 sdb1 part\n");
+	assert!(format_rows(&TreeFormatter::new(vec![Column::Name, Column::Type]), &blocks) ==
+		"NAME   TYPE\n\
+		 sda    disk\n\
+		 \u{251C}\u{2500}sda1 part\n\
+		 \u{2514}\u{2500}sda2 part\n\
+		 sdb    disk\n\
+		 \u{2514}\u{2500}sdb1 part\n");
+}
+
+#[test]
+fn test_tree_formatter_group_separator() {
+	let mut sda = test_block();
+	sda.metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_wwn: Some("0x5000c500a1b2c3d4".to_owned()),
+		..BlockMetadata::default()
+	});
+	let mut sdb = sda.clone();
+	sdb.name = "sdb".to_owned();
+	sdb.partitions.clear();
+	let blocks = [sda, sdb, test_dm_block("dm-0", 0, "vg-root", &[])];
+	let formatter = TreeFormatter { group_separator: Some(Column::Wwn), ..TreeFormatter::new(vec![Column::Name, Column::Wwn]) };
+	assert!(format_rows(&formatter, &blocks) ==
+		"NAME    WWN\n\
+		 sda     0x5000c500a1b2c3d4\n\
+		 \u{251C}\u{2500}sda1  \n\
+		 \u{2514}\u{2500}sda2  \n\
+		 sdb     0x5000c500a1b2c3d4\n\
+		 \n\
+		 vg-root \n");
+	let formatter = TreeFormatter { tree_column: None, ..formatter };
+	assert!(format_rows(&formatter, &blocks) ==
+		"NAME    WWN\n\
+		 sda     0x5000c500a1b2c3d4\n\
+		 sda1    \n\
+		 sda2    \n\
+		 sdb     0x5000c500a1b2c3d4\n\
+		 \n\
+		 vg-root \n");
 }
 
-fn format_major_minor(majmin: &MajorMinor) -> String {
-	return format!("{:>3}:{:<3}", majmin.major, majmin.minor);
+#[test]
+fn test_tree_formatter_noheadings() {
+	let output = format_rows(&TreeFormatter {
+		headings: false,
+		..TreeFormatter::new(column::DEFAULT_COLUMNS.to_vec())
+	}, &[test_block()]);
+	assert!(output ==
+		"sda      8:0    0   32G  0        disk \n\
+		 \u{251C}\u{2500}sda1   8:1    0   57M  0 vfat   part /boot\n\
+		 \u{2514}\u{2500}sda2   8:2    0   32G  0        part /\n");
+}
+
+#[test]
+fn test_tree_formatter_list() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &["sda2"])];
+	let formatter = TreeFormatter {
+		tree_column: None,
+		..TreeFormatter::new(vec![Column::Name, Column::MajMin, Column::Type])
+	};
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &build_tree(block_rows(&blocks, false))).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME    MAJ:MIN TYPE\n\
+		 sda       8:0   disk\n\
+		 sda1      8:1   part\n\
+		 sda2      8:2   part\n\
+		 vg-root 253:0   dm\n");
+}
+
+#[test]
+fn test_tree_formatter_width() {
+	let mut block = test_block();
+	block.partitions[0].mountpoint = "/boot/efi/firmware".to_owned();
+	let formatter = TreeFormatter {
+		width: Some(24),
+		..TreeFormatter::new(vec![Column::Name, Column::Size, Column::Mountpoint])
+	};
+	assert!(format_rows(&formatter, &[block.clone()]) ==
+		"NAME    SIZE MOUNTPOINT\n\
+		 sda      32G \n\
+		 \u{251C}\u{2500}sda1   57M /boot/efi/\u{2026}\n\
+		 \u{2514}\u{2500}sda2   32G /\n");
+
+	let formatter = TreeFormatter {
+		width: Some(10),
+		tree: &ASCII_TREE,
+		..TreeFormatter::new(vec![Column::Name, Column::Size, Column::Mountpoint])
+	};
+	assert!(format_rows(&formatter, &[block]) ==
+		"NAME    SIZE MOUNTPOINT\n\
+		 sda      32G \n\
+		 |-sda1   57M /boot/e...\n\
+		 `-sda2   32G /\n");
+}
+
+#[test]
+fn test_tree_formatter_color() {
+	let mut block = test_block();
+	block.partitions[1].readonly = Some(1);
+	let output = format_rows(&TreeFormatter {
+		color: true,
+		..TreeFormatter::new(vec![Column::Name, Column::Mountpoint, Column::Size])
+	}, &[block]);
+	assert!(output ==
+		"NAME   MOUNTPOINT  SIZE\n\
+		 \x1b[1msda\x1b[0m                 32G\n\
+		 \u{251C}\u{2500}sda1 \x1b[32m/boot\x1b[0m        57M\n\
+		 \u{2514}\u{2500}\x1b[31msda2\x1b[0m \x1b[32m/\x1b[0m            32G\n");
+
+	// A disk below another device in the inverse tree is bold, but its
+	// branch is not.
+	let rows = build_inverse_tree(block_rows(&[test_block(), test_dm_block("dm-0", 0, "vg-root", &["sda"])], false));
+	let mut out = Vec::new();
+	TreeFormatter {
+		color: true,
+		tree: &ASCII_TREE,
+		..TreeFormatter::new(vec![Column::Name])
+	}.write_rows(&mut out, &rows).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME\n\
+		 sda1\n`-\x1b[1msda\x1b[0m\n\
+		 sda2\n`-\x1b[1msda\x1b[0m\n\
+		 vg-root\n`-\x1b[1msda\x1b[0m\n");
+}
+
+#[test]
+fn test_tree_formatter_bytes() {
+	let mut block = test_block();
+	block.partitions[0].size = None;
+	let output = format_rows(&TreeFormatter {
+		cells: CellFormat { bytes: true, ..CellFormat::default() },
+		..TreeFormatter::new(column::DEFAULT_COLUMNS.to_vec())
+	}, &[block]);
+	assert!(output ==
+		"NAME   MAJ:MIN RM        SIZE RO FSTYPE TYPE MOUNTPOINT\n\
+		 sda      8:0    0 34359738368  0        disk \n\
+		 \u{251C}\u{2500}sda1   8:1    0              0 vfat   part /boot\n\
+		 \u{2514}\u{2500}sda2   8:2    0 34299674624  0        part /\n");
+}
+
+#[test]
+fn test_tree_formatter_fs() {
+	let mut block = test_block();
+	block.partitions[1].metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_fs_type: Some("ext4".to_owned()),
+		id_fs_uuid: Some("eca1e7f9-42c7-49b7-9f42-bec0c3e975e6".to_owned()),
+		id_fs_label: Some("root".to_owned()),
+		..BlockMetadata::default()
+	});
+	let output = format_rows(&TreeFormatter::new(column::FS_COLUMNS.to_vec()), &[block]);
+	assert!(output ==
+		"NAME   FSTYPE LABEL UUID                                 MOUNTPOINT\n\
+		 sda                                                      \n\
+		 \u{251C}\u{2500}sda1 vfat                                              /boot\n\
+		 \u{2514}\u{2500}sda2 ext4   root  eca1e7f9-42c7-49b7-9f42-bec0c3e975e6 /\n");
+}
+
+#[test]
+fn test_tree_formatter_wide_label() {
+	let mut block = test_block();
+	block.partitions[0].metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_fs_label: Some("\u{6570}\u{636E}".to_owned()),
+		..BlockMetadata::default()
+	});
+	block.partitions[1].metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_fs_label: Some("cafe\u{301}".to_owned()),
+		..BlockMetadata::default()
+	});
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Label, Column::Size]), &[block]);
+	assert!(output ==
+		"NAME   LABEL  SIZE\n\
+		 sda            32G\n\
+		 \u{251C}\u{2500}sda1 \u{6570}\u{636E}    57M\n\
+		 \u{2514}\u{2500}sda2 cafe\u{301}    32G\n");
 }
 
 #[test]
-fn test_format_major_minor() {
-	assert!(format_major_minor(&MajorMinor { major:   1, minor:   0 }) == "  1:0  ");
-	assert!(format_major_minor(&MajorMinor { major:  10, minor:   0 }) == " 10:0  ");
-	assert!(format_major_minor(&MajorMinor { major:   1, minor:  20 }) == "  1:20 ");
-	assert!(format_major_minor(&MajorMinor { major: 100, minor:  20 }) == "100:20 ");
-	assert!(format_major_minor(&MajorMinor { major: 100, minor: 200 }) == "100:200");
+fn test_tree_formatter_kname() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Kname]), &blocks);
+	assert!(output ==
+		"NAME    KNAME\n\
+		 sda     sda\n\
+		 \u{251C}\u{2500}sda1  sda1\n\
+		 \u{2514}\u{2500}sda2  sda2\n\
+		 vg-root dm-0\n");
+}
+
+#[test]
+fn test_tree_formatter_columns() {
+	let output = format_rows(&TreeFormatter::new(vec![Column::Mountpoint, Column::Size, Column::Name]), &[test_block()]);
+	assert!(output ==
+		"MOUNTPOINT  SIZE NAME\n\
+		 \x20            32G sda\n\
+		 /boot        57M \u{251C}\u{2500}sda1\n\
+		 /            32G \u{2514}\u{2500}sda2\n");
+}
+
+#[test]
+fn test_tree_formatter_tree_column() {
+	let output = format_rows(&TreeFormatter {
+		tree_column: Some(Column::Type),
+		..TreeFormatter::new(vec![Column::Size, Column::Type, Column::Name])
+	}, &[test_block()]);
+	assert!(output ==
+		" SIZE TYPE   NAME\n\
+		 \x20 32G disk   sda\n\
+		 \x20 57M \u{251C}\u{2500}part sda1\n\
+		 \x20 32G \u{2514}\u{2500}part sda2\n");
+}
+
+#[test]
+fn test_tree_formatter_fs_members() {
+	let btrfs = BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_fs_type: Some("btrfs".to_owned()),
+		id_fs_uuid: Some("0b56138b-6124-4ec4-a7a3-7c503516a65c".to_owned()),
+		..BlockMetadata::default()
+	};
+	let mut block = test_block();
+	block.partitions[1].metadata = Some(btrfs.clone());
+	let mut sdb = test_block();
+	sdb.name = "sdb".to_owned();
+	sdb.partitions.clear();
+	sdb.metadata = Some(btrfs);
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::FsType, Column::FsMembers]), &[block, sdb]);
+	assert!(output ==
+		"NAME   FSTYPE FSMEMBERS\n\
+		 sda           \n\
+		 \u{251C}\u{2500}sda1 vfat   \n\
+		 \u{2514}\u{2500}sda2 btrfs  sda2,sdb\n\
+		 sdb    btrfs  sda2,sdb\n");
+}
+
+#[test]
+fn test_tree_formatter_rota() {
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Rota, Column::Ro, Column::Tran]), &[test_block()]);
+	assert!(output ==
+		"NAME   ROTA RO TRAN\n\
+		 sda       1  0 sata\n\
+		 \u{251C}\u{2500}sda1    1  0 \n\
+		 \u{2514}\u{2500}sda2    1  0 \n");
+}
+
+#[test]
+fn test_tree_formatter_model() {
+	let mut block = test_block();
+	let meta = BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_model: Some("Samsung\\x20SSD\\x20850".to_owned()),
+		id_vendor: Some("ATA\\x20\\x20\\x20\\x20\\x20".to_owned()),
+		..BlockMetadata::default()
+	};
+	block.metadata = Some(meta.clone());
+	block.partitions[1].metadata = Some(meta);
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Vendor, Column::Model]), &[block]);
+	assert!(output ==
+		"NAME   VENDOR MODEL\n\
+		 sda    ATA    Samsung SSD 850\n\
+		 \u{251C}\u{2500}sda1        \n\
+		 \u{2514}\u{2500}sda2        \n");
+}
+
+#[test]
+fn test_tree_formatter_serial() {
+	let mut block = test_block();
+	block.metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_serial: Some("Samsung_SSD_850_EVO_250GB_S21PNXAG441234A".to_owned()),
+		id_serial_short: Some("S21PNXAG441234A".to_owned()),
+		..BlockMetadata::default()
+	});
+	let mut usb = test_block();
+	usb.name = "sdb".to_owned();
+	usb.partitions.clear();
+	usb.metadata = Some(BlockMetadata {
+		id_type: "disk".to_owned(),
+		id_serial: Some("Generic_Flash_Disk_8A3F".to_owned()),
+		..BlockMetadata::default()
+	});
+	let mut dm = test_dm_block("dm-0", 0, "vg-root", &[]);
+	dm.metadata = Some(BlockMetadata::default());
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Serial]), &[block, usb, dm]);
+	assert!(output ==
+		"NAME    SERIAL\n\
+		 sda     S21PNXAG441234A\n\
+		 \u{251C}\u{2500}sda1  \n\
+		 \u{2514}\u{2500}sda2  \n\
+		 sdb     Generic_Flash_Disk_8A3F\n\
+		 vg-root \n");
+}
+
+#[test]
+fn test_tree_formatter_backing_file() {
+	let block = |name : &str, backing_file : Option<&str>| Block {
+		name: name.to_owned(),
+		majmin: MajorMinor { major: 7, minor: 0 },
+		partitions: Vec::new(),
+		dm_name: None,
+		backing_file: backing_file.map(|file| file.to_owned()),
+		..test_dm_block(name, 0, "", &[])
+	};
+	let blocks = [block("loop0", Some("/var/lib/snapd/snaps/core_1234.snap")), block("loop1", None)];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::BackFile]), &blocks);
+	assert!(output ==
+		"NAME  BACK-FILE\n\
+		 loop0 /var/lib/snapd/snaps/core_1234.snap\n\
+		 loop1 \n");
+}
+
+#[test]
+fn test_tree_formatter_zram() {
+	let zram = Block {
+		name: "zram0".to_owned(),
+		majmin: MajorMinor { major: 252, minor: 0 },
+		dm_name: None,
+		zram: Some(ZramStat { data: 6807552, compressed: 1467417 }),
+		..test_dm_block("zram0", 0, "", &[])
+	};
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Data, Column::Compr]), &[zram, test_block()]);
+	assert!(output ==
+		"NAME    DATA COMPR\n\
+		 zram0   6.5M  1.4M\n\
+		 sda               \n\
+		 \u{251C}\u{2500}sda1            \n\
+		 \u{2514}\u{2500}sda2            \n");
+}
+
+#[test]
+fn test_tree_formatter_start() {
+	let columns = vec![Column::Name, Column::Start];
+	let output = format_rows(&TreeFormatter::new(columns.clone()), &[test_block()]);
+	assert!(output ==
+		"NAME    START\n\
+		 sda          \n\
+		 \u{251C}\u{2500}sda1   2048\n\
+		 \u{2514}\u{2500}sda2 119360\n");
+
+	let output = format_rows(&TreeFormatter {
+		cells: CellFormat { bytes: true, ..CellFormat::default() },
+		..TreeFormatter::new(columns)
+	}, &[test_block()]);
+	assert!(output ==
+		"NAME      START\n\
+		 sda            \n\
+		 \u{251C}\u{2500}sda1  1048576\n\
+		 \u{2514}\u{2500}sda2 61112320\n");
 }
 
-fn pretty_removable(removable : Option<u64>) -> &'static str {
-	match removable {
-		Some(0) => " 0",
-		Some(_) => " 1",
-		None => "  ",
+#[test]
+fn test_tree_formatter_verbose_flags() {
+	let mut block = test_block();
+	block.partitions[1].readonly = Some(1);
+	let columns = vec![Column::Name, Column::Rm, Column::Ro];
+	let output = format_rows(&TreeFormatter::new(columns.clone()), &[block.clone()]);
+	assert!(output ==
+		"NAME   RM RO\n\
+		 sda     0  0\n\
+		 \u{251C}\u{2500}sda1  0  0\n\
+		 \u{2514}\u{2500}sda2  0  1\n");
+
+	let output = format_rows(&TreeFormatter {
+		cells: CellFormat { verbose_flags: true, ..CellFormat::default() },
+		..TreeFormatter::new(columns)
+	}, &[block]);
+	assert!(output ==
+		"NAME   RM  RO\n\
+		 sda    no  no\n\
+		 \u{251C}\u{2500}sda1 no  no\n\
+		 \u{2514}\u{2500}sda2 no yes\n");
+}
+
+#[test]
+fn test_tree_formatter_owner() {
+	let mut block = test_block();
+	block.node = Some(DeviceNode { uid: 0, gid: 0, mode: 0o060660 });
+	block.partitions[0].node = Some(DeviceNode { uid: 4000000000, gid: 0, mode: 0o060600 });
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Owner, Column::Group, Column::Mode]), &[block]);
+	assert!(output ==
+		"NAME   OWNER      GROUP MODE\n\
+		 sda    root       root  brw-rw----\n\
+		 \u{251C}\u{2500}sda1 4000000000 root  brw-------\n\
+		 \u{2514}\u{2500}sda2                  \n");
+}
+
+#[test]
+fn test_tree_formatter_depth() {
+	let mut block = test_block();
+	block.partitions[0].holders = vec!["dm-0".to_owned()];
+	let mut crypt = test_dm_block("dm-0", 0, "luks", &["sda1"]);
+	crypt.holders = vec!["dm-1".to_owned()];
+	let blocks = [block, crypt, test_dm_block("dm-1", 1, "vg-root", &["dm-0"])];
+	let formatter = TreeFormatter::new(vec![Column::Name, Column::Type]);
+	let tree = |depth| {
+		let mut rows = build_tree(block_rows(&blocks, false));
+		limit_depth(&mut rows, depth);
+		let mut out = Vec::new();
+		formatter.write_rows(&mut out, &rows).unwrap();
+		String::from_utf8(out).unwrap()
+	};
+
+	assert!(tree(1) ==
+		"NAME   TYPE\n\
+		 sda    disk\n\
+		 \u{251C}\u{2500}sda1 part\n\
+		 \u{2502} \u{2514}\u{2500}\u{2026}  \n\
+		 \u{2514}\u{2500}sda2 part\n");
+	assert!(tree(2) ==
+		"NAME     TYPE\n\
+		 sda      disk\n\
+		 \u{251C}\u{2500}sda1   part\n\
+		 \u{2502} \u{2514}\u{2500}luks dm\n\
+		 \u{2502}   \u{2514}\u{2500}\u{2026}  \n\
+		 \u{2514}\u{2500}sda2   part\n");
+	assert!(!tree(3).contains('\u{2026}'));
+}
+
+#[test]
+fn test_tree_formatter_alignment() {
+	let mut block = test_block();
+	block.partitions[1].alignment_offset = Some(3584);
+	let mut dm = test_dm_block("dm-0", 0, "vg-root", &[]);
+	dm.alignment_offset = None;
+	let output = format_rows(&TreeFormatter {
+		color: true,
+		..TreeFormatter::new(vec![Column::Name, Column::Alignment])
+	}, &[block, dm]);
+	assert!(output ==
+		"NAME    ALIGNMENT\n\
+		 \x1b[1msda\x1b[0m             0\n\
+		 \u{251C}\u{2500}sda1          0\n\
+		 \u{2514}\u{2500}sda2       \x1b[31m3584\x1b[0m\n\
+		 vg-root          \n");
+}
+
+#[test]
+fn test_tree_formatter_state() {
+	let mut offline = test_block();
+	offline.name = "sdb".to_owned();
+	offline.state = Some("offline".to_owned());
+	offline.partitions.clear();
+	let blocks = [test_block(), offline, test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::State]), &blocks);
+	assert!(output ==
+		"NAME    STATE\n\
+		 sda     running\n\
+		 \u{251C}\u{2500}sda1  \n\
+		 \u{2514}\u{2500}sda2  \n\
+		 sdb     offline\n\
+		 vg-root \n");
+}
+
+#[test]
+fn test_tree_formatter_hotplug() {
+	let mut usb = test_block();
+	usb.name = "sdb".to_owned();
+	usb.removable = Some(1);
+	usb.hotplug = Some(1);
+	usb.partitions.truncate(1);
+	let mut unknown = test_dm_block("dm-0", 0, "vg-root", &[]);
+	unknown.hotplug = None;
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Rm, Column::Hotplug]), &[usb, unknown]);
+	assert!(output ==
+		"NAME    RM HOTPLUG\n\
+		 sdb      1       1\n\
+		 \u{2514}\u{2500}sda1   1       1\n\
+		 vg-root  0        \n");
+}
+
+#[test]
+fn test_tree_formatter_discard() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::DiscGran, Column::DiscMax]), &blocks);
+	assert!(output ==
+		"NAME    DISC-GRAN DISC-MAX\n\
+		 sda            0B       0B\n\
+		 \u{251C}\u{2500}sda1         0B       0B\n\
+		 \u{2514}\u{2500}sda2         0B       0B\n\
+		 vg-root        4K       2G\n");
+}
+
+#[test]
+fn test_tree_formatter_dax() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Dax]), &blocks);
+	assert!(output ==
+		"NAME    DAX\n\
+		 sda       0\n\
+		 \u{251C}\u{2500}sda1    0\n\
+		 \u{2514}\u{2500}sda2    0\n\
+		 vg-root    \n");
+}
+
+#[test]
+fn test_tree_formatter_sector_sizes() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::PhySec, Column::LogSec]), &blocks);
+	assert!(output ==
+		"NAME    PHY-SEC LOG-SEC\n\
+		 sda        4096     512\n\
+		 \u{251C}\u{2500}sda1     4096     512\n\
+		 \u{2514}\u{2500}sda2     4096     512\n\
+		 vg-root     512     512\n");
+}
+
+#[test]
+fn test_tree_formatter_io_sizes() {
+	// An optimal I/O size of zero means none was given, so it is left blank.
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::MinIo, Column::OptIo]), &blocks);
+	assert!(output ==
+		"NAME    MIN-IO OPT-IO\n\
+		 sda       4096       \n\
+		 \u{251C}\u{2500}sda1    4096       \n\
+		 \u{2514}\u{2500}sda2    4096       \n\
+		 vg-root  65536 131072\n");
+}
+
+#[test]
+fn test_tree_formatter_scheduler() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Sched]), &blocks);
+	assert!(output ==
+		"NAME    SCHED\n\
+		 sda     mq-deadline\n\
+		 \u{251C}\u{2500}sda1  mq-deadline\n\
+		 \u{2514}\u{2500}sda2  mq-deadline\n\
+		 vg-root \n");
+}
+
+#[test]
+fn test_tree_formatter_mount_options() {
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Mountpoint, Column::MountOptions]), &[test_block()]);
+	assert!(output ==
+		"NAME   MOUNTPOINT MOUNTOPTIONS\n\
+		 sda               \n\
+		 \u{251C}\u{2500}sda1 /boot      rw,relatime\n\
+		 \u{2514}\u{2500}sda2 /          rw,noatime,errors=remount-ro\n");
+}
+
+#[test]
+fn test_tree_formatter_fsro() {
+	let mut block = test_block();
+	block.partitions[0].mount_options = "ro,relatime".to_owned();
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Ro, Column::FsRo]), &[block]);
+	assert!(output ==
+		"NAME   RO FSRO\n\
+		 sda     0     \n\
+		 \u{251C}\u{2500}sda1  0    1\n\
+		 \u{2514}\u{2500}sda2  0    0\n");
+}
+
+#[test]
+fn test_tree_formatter_mountpoints() {
+	let output = format_rows(&TreeFormatter::new(vec![Column::Name, Column::Mountpoints, Column::Size]), &[test_block()]);
+	assert!(output ==
+		"NAME   MOUNTPOINTS      SIZE\n\
+		 sda                      32G\n\
+		 \u{251C}\u{2500}sda1 /boot             57M\n\
+		 \u{2514}\u{2500}sda2 /                 32G\n\
+		 \x20      /home                \n\
+		 \x20      /var/lib/docker      \n");
+}
+
+#[test]
+fn test_tree_formatter_fs_usage() {
+	let mut rows = block_rows(&[test_block()], false);
+	rows[0].children[1].fs_usage = Some(FsUsage { size: 34299674624, avail: 25769803776, used: 8529870848 });
+
+	let formatter = TreeFormatter::new(vec![Column::Name, Column::FsSize, Column::FsAvail, Column::FsUse]);
+	let mut out = Vec::new();
+	formatter.write_rows(&mut out, &rows).unwrap();
+	assert!(String::from_utf8(out).unwrap() ==
+		"NAME   FSSIZE FSAVAIL FSUSE%\n\
+		 sda                         \n\
+		 \u{251C}\u{2500}sda1                      \n\
+		 \u{2514}\u{2500}sda2    32G     24G    25%\n");
+}
+
+#[test]
+fn test_mountpoint_usage() {
+	assert!(mountpoint_usage("").is_none());
+	assert!(mountpoint_usage("[SWAP]").is_none());
+	assert!(mountpoint_usage("/").is_some());
+}
+
+#[test]
+fn test_pairs_formatter() {
+	let mut block = test_block();
+	block.partitions[0].mountpoint = "/mnt/my \"boot\"".to_owned();
+	let output = format_rows(&PairsFormatter {
+		columns: column::DEFAULT_COLUMNS.to_vec(),
+		cells: CellFormat { bytes: true, ..CellFormat::default() },
+		terminator: "\n",
+	}, &[block]);
+	assert!(output ==
+		"NAME=\"sda\" MAJ:MIN=\"8:0\" RM=\"0\" SIZE=\"34359738368\" RO=\"0\" FSTYPE=\"\" TYPE=\"disk\" MOUNTPOINT=\"\"\n\
+		 NAME=\"sda1\" MAJ:MIN=\"8:1\" RM=\"0\" SIZE=\"60063744\" RO=\"0\" FSTYPE=\"vfat\" TYPE=\"part\" MOUNTPOINT=\"/mnt/my \\x22boot\\x22\"\n\
+		 NAME=\"sda2\" MAJ:MIN=\"8:2\" RM=\"0\" SIZE=\"34299674624\" RO=\"0\" FSTYPE=\"\" TYPE=\"part\" MOUNTPOINT=\"/\"\n");
+}
+
+#[test]
+fn test_pairs_formatter_raw() {
+	let mut block = test_block();
+	block.partitions[0].mountpoint = "/mnt/my disk".to_owned();
+	block.partitions[1].size = None;
+	let output = format_rows(&PairsFormatter {
+		columns: vec![Column::Name, Column::Size, Column::Label, Column::Mountpoint],
+		cells: CellFormat { bytes: true, ..CellFormat::default() },
+		terminator: "\n",
+	}, &[block]);
+	assert!(output ==
+		"NAME=\"sda\" SIZE=\"34359738368\" LABEL=\"\" MOUNTPOINT=\"\"\n\
+		 NAME=\"sda1\" SIZE=\"60063744\" LABEL=\"\" MOUNTPOINT=\"/mnt/my disk\"\n\
+		 NAME=\"sda2\" SIZE=\"\" LABEL=\"\" MOUNTPOINT=\"/\"\n");
+}
+
+#[test]
+fn test_formatter_zero() {
+	let mut block = test_block();
+	block.partitions[1].mountpoint = "/mnt/a\nb".to_owned();
+	let output = format_rows(&PairsFormatter {
+		columns: vec![Column::Name, Column::Mountpoint],
+		cells: CellFormat::default(),
+		terminator: "\0",
+	}, &[block]);
+	assert!(output.matches('\0').count() == 3);
+	assert!(!output.contains('\n'));
+	assert!(output.ends_with("NAME=\"sda2\" MOUNTPOINT=\"/mnt/a\\x0ab\"\0"));
+
+	let output = format_rows(&TreeFormatter {
+		terminator: "\0",
+		tree_column: None,
+		..TreeFormatter::new(vec![Column::Name, Column::Mountpoints])
+	}, &[test_block()]);
+	assert!(output.matches('\0').count() == 4);
+	assert!(output.ends_with("sda2 /\n     /home\n     /var/lib/docker\0"));
+}
+
+#[test]
+fn test_json_formatter() {
+	let output = format_rows(&JsonFormatter { columns: column::DEFAULT_COLUMNS.to_vec(), flat: false }, &[test_block()]);
+	assert!(output.starts_with("{\n   \"blockdevices\": [\n      {\n         \"name\": \"sda\",\n"));
+	assert!(output.contains("\"maj:min\": \"8:1\""));
+	assert!(output.contains("\"size\": 60063744,"));
+	assert!(output.contains("\"rm\": false,"));
+	assert!(!output.contains("\"rota\""));
+	assert!(output.contains("\"fstype\": null,"));
+	assert!(output.contains("\"fstype\": \"vfat\","));
+	assert!(output.contains("\"mountpoint\": null,\n         \"children\": ["));
+	assert!(output.contains("\"mountpoint\": \"/boot\"\n"));
+
+	let output = format_rows(&JsonFormatter { columns: vec![Column::Mountpoints], flat: false }, &[test_block()]);
+	assert!(output.contains("\"mountpoints\": [\n                  \"/\",\n                  \"/home\",\n"));
+}
+
+#[test]
+fn test_json_formatter_flat() {
+	let columns = vec![Column::Name, Column::Type];
+	let nested = format_rows(&JsonFormatter { columns: columns.clone(), flat: false }, &[test_block()]);
+	assert!(nested == "\
+{
+   \"blockdevices\": [
+      {
+         \"name\": \"sda\",
+         \"type\": \"disk\",
+         \"children\": [
+            {
+               \"name\": \"sda1\",
+               \"type\": \"part\"
+            },
+            {
+               \"name\": \"sda2\",
+               \"type\": \"part\"
+            }
+         ]
+      }
+   ]
+}
+");
+	let flat = format_rows(&JsonFormatter { columns, flat: true }, &[test_block()]);
+	assert!(flat == "\
+{
+   \"blockdevices\": [
+      {
+         \"name\": \"sda\",
+         \"type\": \"disk\",
+         \"parent\": null
+      },
+      {
+         \"name\": \"sda1\",
+         \"type\": \"part\",
+         \"parent\": \"sda\"
+      },
+      {
+         \"name\": \"sda2\",
+         \"type\": \"part\",
+         \"parent\": \"sda\"
+      }
+   ]
+}
+");
+}
+
+#[test]
+fn test_json_lines_formatter() {
+	let blocks = [test_block(), test_dm_block("dm-0", 0, "vg-root", &[])];
+	let output = format_rows(&JsonLinesFormatter { json: JsonFormatter { columns: column::DEFAULT_COLUMNS.to_vec(), flat: false } }, &blocks);
+	let lines = output.lines().collect::<Vec<_>>();
+	assert!(lines.len() == blocks.len());
+	assert!(lines[0].starts_with("{\"name\":\"sda\",\"maj:min\":\"8:0\","));
+	assert!(lines[0].contains("\"children\":[{\"name\":\"sda1\","));
+	assert!(lines[1].starts_with("{\"name\":\"vg-root\","));
+	assert!(output.ends_with("}\n"));
+}
+
+/// The totals printed by `--summary`.
+#[derive(Debug)]
+#[derive(Default)]
+#[derive(PartialEq)]
+struct Summary {
+	disks : usize,
+	capacity : u64,
+	mounted : u64,
+}
+
+/// Whether `block` is a disk in its own right, rather than a volume built on
+/// other devices, a file or memory made to look like a disk, or a disk
+/// served over the network.
+fn is_physical_disk(block : &Block) -> bool {
+	block.slaves.is_empty() && !filter::is_pseudo(block) && !block.name.starts_with("nbd")
+}
+
+/// Adds up the disks in `blocks` that are among `shown`, the sorted kernel
+/// names of the devices being printed. Devices built on other devices, such
+/// as LVM volumes and RAID arrays, take their space from the disks beneath
+/// them so are not counted as disks, but their size counts as mounted when
+/// they are. A disk that is mounted whole counts in full, and otherwise
+/// each of its mounted partitions does. Loop, zram and network devices are
+/// left out altogether, as a loop device's space is already counted on the
+/// disk that holds its file.
+fn summarize(blocks : &[Block], shown : &[String]) -> Summary {
+	let is_shown = |name : &String| shown.binary_search(name).is_ok();
+	let mut summary = Summary::default();
+	for block in blocks.iter().filter(|block| is_physical_disk(block) || !block.slaves.is_empty()) {
+		if block.slaves.is_empty() && is_shown(&block.name) {
+			summary.disks += 1;
+			summary.capacity += block.size.unwrap_or(0);
+		}
+		summary.mounted += if !block.mountpoint.is_empty() {
+			if is_shown(&block.name) { block.size.unwrap_or(0) } else { 0 }
+		} else {
+			block.partitions.iter()
+				.filter(|part| !part.mountpoint.is_empty() && is_shown(&part.name))
+				.map(|part| part.size.unwrap_or(0))
+				.sum()
+		};
 	}
+	summary
 }
 
-fn pretty_units(size : u64, power : u32, precision : usize, suffix : &str) -> String {
-	let divisor = (1024 as u64).pow(power) as f64;
-	let n = (size as f64) / divisor;
+#[test]
+fn test_summarize() {
+	let mut sdb = test_block();
+	sdb.name = "sdb".to_owned();
+	for part in &mut sdb.partitions {
+		part.name = part.name.replace("sda", "sdb");
+		part.mountpoint = String::new();
+	}
+	let mut srv = test_dm_block("dm-0", 0, "vg-srv", &["sdb2"]);
+	srv.mountpoint = "/srv".to_owned();
+	// An image mounted from a file on sda2, whose space sda2 already counts.
+	let mut loop0 = test_block();
+	loop0.name = "loop0".to_owned();
+	loop0.majmin = MajorMinor { major: 7, minor: 0 };
+	loop0.size = Some(1073741824);
+	loop0.partitions.clear();
+	loop0.backing_file = Some("/var/lib/images/disk.img".to_owned());
+	loop0.mountpoint = "/mnt/image".to_owned();
+	let blocks = [test_block(), sdb, srv, loop0];
+	let rows = build_tree(block_rows(&blocks, false));
+	let summary = summarize(&blocks, &row_knames(&rows));
+	assert!(summary == Summary { disks: 2, capacity: 2 * 34359738368, mounted: 60063744 + 34299674624 + 10737418240 });
+	assert!(summarize(&[], &[]) == Summary::default());
 
-	format!("{0:>4.1$}{2}", n, precision, suffix)
+	// `lsblk --summary /dev/sdb` counts only sdb and what is built on it.
+	let summary = summarize(&blocks, &row_knames(&select_rows(&rows, &["sdb".to_owned()])));
+	assert!(summary == Summary { disks: 1, capacity: 34359738368, mounted: 10737418240 });
+	let summary = summarize(&blocks, &row_knames(&select_rows(&rows, &["sda1".to_owned()])));
+	assert!(summary == Summary { disks: 0, capacity: 0, mounted: 60063744 });
 }
 
-fn pretty_size(size: Option<u64>) -> String {
-	match size {
-		Some(size) => match size {
-			size if size < 1024 => format!("{:>5}", size),
-			size if size <= ((1024 as u64).pow(2)) => pretty_units(size, 1, 0, "K"),
-			size if size <= ((1024 as u64).pow(3)) => pretty_units(size, 2, 1, "M"),
-			size if size <= ((1024 as u64).pow(4)) => pretty_units(size, 3, 0, "G"),
-			size if size <= ((1024 as u64).pow(5)) => pretty_units(size, 4, 0, "T"),
-			size if size <= ((1024 as u64).pow(6)) => pretty_units(size, 5, 0, "P"),
-			size if size <= ((1024 as u64).pow(7)) => pretty_units(size, 6, 0, "E"),
-			size if size <= ((1024 as u64).pow(8)) => pretty_units(size, 7, 0, "Z"),
-			_ => "big".into(),
-		},
-		None => "     ".into(),
+fn summary_line(summary : &Summary, cells : &CellFormat) -> String {
+	format!("{} {}, {} total, {} mounted",
+		summary.disks,
+		if summary.disks == 1 { "disk" } else { "disks" },
+		cells.size(Some(summary.capacity)).trim_start(),
+		cells.size(Some(summary.mounted)).trim_start())
+}
+
+#[test]
+fn test_summary_line() {
+	let summary = Summary { disks: 2, capacity: 2 * 34359738368, mounted: 45097156608 };
+	assert!(summary_line(&summary, &CellFormat::default()) == "2 disks, 64G total, 42G mounted");
+	let cells = CellFormat { bytes: true, ..CellFormat::default() };
+	assert!(summary_line(&Summary { disks: 1, capacity: 512, mounted: 0 }, &cells) == "1 disk, 512 total, 0 mounted");
+}
+
+/// Scans for block devices and arranges them for printing as `opts` asks.
+fn scan_rows(opts : &Options, columns : &[Column]) -> Result<(Vec<Block>, Vec<Row>), lsblk::LsblkError> {
+	let mut ctx = lsblk::Context::default();
+	ctx.probe = opts.probe;
+	let knames = opts.devices.iter().map(|device| lsblk::resolve_device(&ctx, device)).collect::<Result<Vec<_>, _>>()?;
+	let mut blocks = lsblk::list_block_devices_in(&ctx)?;
+	match opts.include {
+		Some(ref majors) => filter::include_majors(&mut blocks, majors),
+		// Devices asked for by name are shown even if they would usually be
+		// hidden.
+		None if !knames.is_empty() => filter::exclude_majors(&mut blocks, opts.exclude.as_ref().map_or(&[], |majors| majors)),
+		None => filter::exclude_majors(&mut blocks, opts.exclude.as_ref().map_or(filter::DEFAULT_EXCLUDE, |majors| majors)),
+	}
+	if opts.removable_only {
+		filter::removable_only(&mut blocks);
+	}
+	if opts.hide_empty {
+		filter::hide_empty(&mut blocks);
+	}
+	if opts.exclude_zero_size {
+		filter::exclude_zero_size(&mut blocks);
+	}
+	if opts.exclude_pseudo {
+		filter::exclude_pseudo(&mut blocks);
+	}
+	if let Some(ref fstypes) = opts.fstypes {
+		filter::include_fstypes(&mut blocks, fstypes);
+	}
+	if let Some(ref fstypes) = opts.exclude_fstypes {
+		filter::exclude_fstypes(&mut blocks, fstypes);
+	}
+	if opts.mounted {
+		filter::mounted_only(&mut blocks);
+	}
+
+	let with_usage = columns.iter().any(|column| column.needs_fs_usage());
+	let mut rows = block_rows(&blocks, with_usage);
+	if opts.inverse {
+		rows = build_inverse_tree(rows);
+	} else if !opts.nodeps {
+		rows = build_tree(rows);
+	}
+	if opts.nodeps {
+		remove_dependents(&mut rows);
+	}
+	if !knames.is_empty() {
+		rows = select_rows(&rows, &knames);
+	}
+	if let Some(blocktype) = opts.exclude_slaves {
+		exclude_slaves(&mut rows, blocktype);
+	}
+	if let Some(depth) = opts.tree_depth {
+		limit_depth(&mut rows, depth);
+	}
+	if let Some(ref columns) = opts.sort {
+		sort_rows(&mut rows, columns, opts.reverse);
+	}
+	if let Some(column) = opts.group_by {
+		group_rows(&mut rows, column);
+	}
+	if let Some(column) = opts.dedup {
+		dedup_rows(&mut rows, column);
+	}
+	Ok((blocks, rows))
+}
+
+/// The kernel names of every device in the tree, sorted and without
+/// repeats.
+fn row_knames(rows : &[Row]) -> Vec<String> {
+	fn collect(rows : &[Row], knames : &mut Vec<String>) {
+		for row in rows {
+			knames.push(row.kname.to_owned());
+			collect(&row.children, knames);
+		}
+	}
+
+	let mut knames = Vec::new();
+	collect(rows, &mut knames);
+	knames.sort();
+	knames.dedup();
+	knames
+}
+
+/// The devices in `current` that were not in `previous`, and the devices in
+/// `previous` that have gone.
+fn device_changes(previous : &[String], current : &[String]) -> (Vec<String>, Vec<String>) {
+	let added = current.iter().filter(|kname| !previous.contains(kname)).cloned().collect();
+	let removed = previous.iter().filter(|kname| !current.contains(kname)).cloned().collect();
+	(added, removed)
+}
+
+#[test]
+fn test_device_changes() {
+	let before = row_knames(&build_tree(block_rows(&[test_block()], false)));
+	assert!(before == ["sda", "sda1", "sda2"]);
+
+	let mut block = test_block();
+	block.partitions.remove(0);
+	let blocks = [block, test_dm_block("dm-0", 0, "mirror", &["sda2"])];
+	let after = row_knames(&build_tree(block_rows(&blocks, false)));
+	assert!(device_changes(&before, &after) == (vec!["dm-0".to_owned()], vec!["sda1".to_owned()]));
+	assert!(device_changes(&after, &after) == (vec![], vec![]));
+}
+
+fn write_changes(out : &mut dyn Write, added : &[String], removed : &[String], color : bool) -> io::Result<()> {
+	let (green, red, reset) = if color { (GREEN, RED, RESET) } else { ("", "", "") };
+	for kname in added {
+		writeln!(out, "{}+{}{}", green, kname, reset)?;
+	}
+	for kname in removed {
+		writeln!(out, "{}-{}{}", red, kname, reset)?;
 	}
+	Ok(())
 }
 
 #[test]
-fn test_pretty_size() {
-	assert!("     " == pretty_size(None));
-	assert!(" 1023" == pretty_size(Some(1023)));
-	assert!("   1K" == pretty_size(Some(1024)));
-	assert!("57.3M" == pretty_size(Some(60063744)));
-	assert!("   4G" == pretty_size(Some(4292870144)));
-	assert!("  28G" == pretty_size(Some(30063722496)));
-	assert!("  32G" == pretty_size(Some(34359738368)));
+fn test_write_changes() {
+	let mut output = Vec::new();
+	write_changes(&mut output, &["sdb".to_owned()], &["sdc".to_owned(), "sdc1".to_owned()], false).unwrap();
+	assert!(output == b"+sdb\n-sdc\n-sdc1\n");
+
+	let mut output = Vec::new();
+	write_changes(&mut output, &["sdb".to_owned()], &[], true).unwrap();
+	assert!(output == b"\x1b[32m+sdb\x1b[0m\n");
+}
+
+const CLEAR_SCREEN : &str = "\x1b[H\x1b[2J";
+
+static INTERRUPTED : AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_ : libc::c_int) {
+	INTERRUPTED.store(true, atomic::Ordering::SeqCst);
+}
+
+/// Prints one listing for `watch`, optionally clearing the screen first and
+/// following it with the devices that were added and removed.
+fn redraw(out : &mut dyn Write, formatter : &dyn Formatter, rows : &[Row], clear : bool, changes : Option<(Vec<String>, Vec<String>)>, color : bool) -> io::Result<()> {
+	if clear {
+		write!(out, "{}", CLEAR_SCREEN)?;
+	}
+	formatter.write_rows(out, rows)?;
+	if let Some((added, removed)) = changes {
+		write_changes(out, &added, &removed, color)?;
+	}
+	out.flush()
 }
 
-fn pretty_readonly(readonly: Option<u64>) -> &'static str {
-	match readonly {
-		Some(0) => " 0",
-		Some(_) => " 1",
-		None => "  ",
+/// Scans and prints the devices every `interval` seconds until interrupted.
+/// On a terminal the screen is cleared first, and the devices that appeared
+/// or went away since the last scan are listed after the devices.
+fn watch(opts : &Options, columns : &[Column], formatter : &dyn Formatter, color : bool, interval : u64) -> Result<(), Box<dyn error::Error>> {
+	unsafe {
+		libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t);
 	}
+	let tty = stdout_is_tty();
+	let mut previous : Option<Vec<String>> = None;
+	while !INTERRUPTED.load(atomic::Ordering::SeqCst) {
+		let (_, rows) = scan_rows(opts, columns)?;
+		let knames = row_knames(&rows);
+		let changes = match previous {
+			Some(ref previous) if tty => Some(device_changes(previous, &knames)),
+			_ => None,
+		};
+		let result = redraw(&mut io::stdout().lock(), formatter, &rows, tty, changes, color);
+		match result {
+			Err(ref err) if err.kind() == ErrorKind::BrokenPipe => return Ok(()),
+			result => result?,
+		}
+		previous = Some(knames);
+
+		let deadline = Instant::now() + Duration::from_secs(interval);
+		while !INTERRUPTED.load(atomic::Ordering::SeqCst) && Instant::now() < deadline {
+			thread::sleep(Duration::from_millis(100));
+		}
+	}
+	Ok(())
+}
+
+fn find_row<'a>(rows : &'a [Row], kname : &str) -> Option<&'a Row> {
+	rows.iter().find_map(|row| if row.kname == kname { Some(row) } else { find_row(&row.children, kname) })
+}
+
+/// A line for `--monitor` announcing a new device, such as `+sdb1 ext4 7.5G part`.
+fn added_line(row : &Row) -> String {
+	let format = CellFormat::default();
+	let mut fields = vec![format!("+{}", row.kname)];
+	fields.extend([Column::FsType, Column::Size, Column::Type].iter()
+		.map(|column| column.text(row, &format).trim().to_owned())
+		.filter(|text| !text.is_empty()));
+	fields.join(" ")
 }
 
 #[test]
-fn test_pretty_readonly() {
-	assert!("  " == pretty_readonly(None));
-	assert!(" 0" == pretty_readonly(Some(0)));
-	assert!(" 1" == pretty_readonly(Some(1)));
-	assert!(" 1" == pretty_readonly(Some(2)));
-	assert!(" 1" == pretty_readonly(Some(1234)));
+fn test_added_line() {
+	let rows = block_rows(&[test_block()], false);
+	assert!(added_line(find_row(&rows, "sda").unwrap()) == "+sda 32G disk");
+	assert!(added_line(find_row(&rows, "sda1").unwrap()) == "+sda1 vfat 57M part");
+	assert!(find_row(&rows, "sdb").is_none());
+}
+
+/// Prints a line whenever a block device is added or removed, until killed.
+/// Added devices are looked up in sysfs as soon as the kernel announces
+/// them, so their filesystem type is only shown if udev has already seen
+/// them, or with `--probe`.
+fn monitor(opts : &Options) -> Result<(), Box<dyn error::Error>> {
+	let socket = uevent::UeventSocket::open().map_err(|err| match err.kind() {
+		ErrorKind::PermissionDenied => format!("cannot listen for device events: {} (try running as root)", err),
+		_ => format!("cannot listen for device events: {}", err),
+	})?;
+	let mut out = io::stdout();
+	loop {
+		let event = match uevent::parse_uevent(&socket.recv()?) {
+			Some(event) => event,
+			None => continue,
+		};
+		let line = match event.action {
+			Action::Add => {
+				let mut ctx = lsblk::Context::default();
+				ctx.probe = opts.probe;
+				let rows = block_rows(&lsblk::list_block_devices_in(&ctx)?, false);
+				find_row(&rows, &event.name).map_or_else(|| format!("+{}", event.name), added_line)
+			},
+			Action::Remove => format!("-{}", event.name),
+		};
+		match writeln!(out, "{}", line).and_then(|()| out.flush()) {
+			Err(ref err) if err.kind() == ErrorKind::BrokenPipe => return Ok(()),
+			result => result?,
+		}
+	}
 }
 
-fn print_blocks(blocks : Vec<Block>) {
-	let mut rows = Vec::new();
+/// Scans for every block device, for `--snapshot` and `--diff`. The
+/// filters that pick which devices to print do not apply.
+#[cfg(feature = "serde")]
+fn scan_blocks(opts : &Options) -> Result<Vec<Block>, lsblk::LsblkError> {
+	let mut ctx = lsblk::Context::default();
+	ctx.probe = opts.probe;
+	lsblk::list_block_devices_in(&ctx)
+}
 
-	for block in blocks {
-		rows.push(Row {
-			name: block.name.to_owned(),
-			majmin: format_major_minor(&block.majmin),
-			removable: pretty_removable(block.removable),
-			size: pretty_size(block.size),
-			readonly: pretty_readonly(block.readonly),
-			row_type: BlockType::Disk,
-			mountpoint: block.mountpoint.to_owned(),
-		});
+/// Prints a line for each device that was added, removed, resized or
+/// remounted since the snapshot at `path` was taken.
+#[cfg(feature = "serde")]
+fn diff(opts : &Options, path : &Path) -> Result<(), Box<dyn error::Error>> {
+	let old = snapshot::read_snapshot(path)?;
+	let new = scan_blocks(opts)?;
+	let format = CellFormat { bytes: opts.bytes, si: opts.si, ..CellFormat::default() };
+	let mut out = io::stdout().lock();
+	for change in snapshot::diff_blocks(&old, &new) {
+		match writeln!(out, "{}", snapshot::change_line(&change, &new, &format)) {
+			Err(ref err) if err.kind() == ErrorKind::BrokenPipe => return Ok(()),
+			result => result?,
+		}
+	}
+	Ok(())
+}
 
-		for (i, part) in block.partitions.iter().enumerate() {
-			let name = if i+1 == block.partitions.len() {
-				format!("\u{2514}\u{2500}{}", part.name)
-			} else {
-				format!("\u{251C}\u{2500}{}", part.name)
-			};
-			rows.push(Row {
-				name: name,
-				majmin: format_major_minor(&part.majmin),
-				removable: pretty_removable(block.removable),
-				size: pretty_size(part.size),
-				readonly: pretty_readonly(part.readonly),
-				row_type: BlockType::Partition,
-				mountpoint: part.mountpoint.to_owned(),
-			});
+/// The columns to print: those given with `-o`, or else the defaults, with
+/// any given with `-o +COLUMN` after them.
+fn select_columns(opts : &Options) -> Vec<Column> {
+	let mut columns = match opts.columns {
+		Some(ref columns) => columns.to_owned(),
+		None if opts.fs => column::FS_COLUMNS.to_vec(),
+		None => column::DEFAULT_COLUMNS.to_vec(),
+	};
+	columns.extend(&opts.extra_columns);
+	columns
+}
+
+#[test]
+fn test_select_columns() {
+	let select = |args : &[&str]| select_columns(&options::parse_args(args.iter().map(|arg| arg.to_string())).unwrap());
+	assert!(select(&[]) == column::DEFAULT_COLUMNS);
+	assert!(select(&["-o", "+UUID"]) == [column::DEFAULT_COLUMNS, &[Column::Uuid]].concat());
+	assert!(select(&["-f", "-o", "+SIZE"]) == [column::FS_COLUMNS, &[Column::Size]].concat());
+	assert!(select(&["-o", "NAME", "-o", "+UUID"]) == [Column::Name, Column::Uuid]);
+}
+
+fn run() -> Result<(), Box<dyn error::Error>> {
+	let mut opts = options::parse_args(env::args().skip(1))?;
+	config::load()?.apply(&mut opts);
+	if opts.monitor {
+		return monitor(&opts);
+	}
+	#[cfg(feature = "serde")]
+	{
+		if let Some(ref path) = opts.snapshot {
+			return Ok(snapshot::write_snapshot(path, &scan_blocks(&opts)?)?);
+		}
+		if let Some(ref path) = opts.diff {
+			return diff(&opts, path);
 		}
 	}
 
-	let name_len = rows.iter().map(|row| row.name.chars().count()).max().unwrap_or(0);
+	let columns = select_columns(&opts);
 
-	println!("{1:<0$} MAJ:MIN RM  SIZE RO TYPE MOUNTPOINT", name_len, "NAME");
-	for row in rows {
-		println!("{1:<0$} {2} {3} {4:>5} {5} {6:<4} {7}",
-			name_len, row.name,
-			row.majmin,
-			row.removable,
-			row.size,
-			row.readonly,
-			describe_block_type(row.row_type),
-			row.mountpoint,
-		);
+	let cells = CellFormat { bytes: opts.bytes, si: opts.si, verbose_flags: opts.verbose_flags };
+	let terminator = if opts.zero { "\0" } else { "\n" };
+	let color = match opts.color {
+		ColorMode::Always => true,
+		ColorMode::Never => false,
+		ColorMode::Auto => stdout_is_tty(),
+	};
+	let formatter : Box<dyn Formatter> = if opts.json {
+		Box::new(JsonFormatter { columns: columns.clone(), flat: opts.json_flat })
+	} else if opts.json_lines {
+		Box::new(JsonLinesFormatter { json: JsonFormatter { columns: columns.clone(), flat: false } })
+	} else if opts.pairs {
+		// Scripts parse -P, so RM and RO stay numeric there.
+		Box::new(PairsFormatter { columns: columns.clone(), cells: CellFormat { verbose_flags: false, ..cells }, terminator })
+	} else {
+		let tree = if opts.ascii || !is_utf8_locale(&ctype_locale()) { &ASCII_TREE } else { &UNICODE_TREE };
+		Box::new(TreeFormatter {
+			cells,
+			tree,
+			headings: !opts.noheadings,
+			terminator,
+			tree_column: if opts.list { None } else { Some(opts.tree.unwrap_or(Column::Name)) },
+			group_separator: if opts.group_separator { opts.group_by } else { None },
+			compact_single_partition: opts.compact_single_partition,
+			color,
+			width: opts.width.or_else(terminal_width),
+			..TreeFormatter::new(columns.clone())
+		})
+	};
+
+	if let Some(interval) = opts.watch {
+		return watch(&opts, &columns, &*formatter, color, interval);
+	}
+
+	let (blocks, rows) = scan_rows(&opts, &columns)?;
+	let summary = if opts.summary {
+		Some(summary_line(&summarize(&blocks, &row_knames(&rows)), &CellFormat { bytes: opts.bytes, si: opts.si, ..CellFormat::default() }))
+	} else {
+		None
+	};
+	let write_output = |out : &mut dyn Write| -> io::Result<()> {
+		formatter.write_rows(out, &rows)?;
+		match summary {
+			Some(ref summary) => writeln!(out, "{}", summary),
+			None => Ok(()),
+		}
+	};
+	let pager = if opts.pager && stdout_is_tty() { spawn_pager() } else { None };
+	let result = match pager {
+		Some(mut child) => {
+			let result = write_output(child.stdin.as_mut().unwrap());
+			drop(child.stdin.take());
+			child.wait()?;
+			result
+		},
+		None => write_output(&mut io::stdout().lock()),
+	};
+
+	match result {
+		Err(ref err) if err.kind() == ErrorKind::BrokenPipe => Ok(()),
+		result => Ok(result?),
 	}
 }
 
 fn main() {
-	let block_root = Path::new("/sys/block");
-	let block_dirs = fs::read_dir(block_root).unwrap();
-	let blocks = block_dirs.filter_map(|dir| {
-		dir.ok().map(read_block)
-	}).filter_map(|block| block).collect::<Vec<_>>();
-	print_blocks(blocks);
+	if let Err(err) = run() {
+		eprintln!("lsblk: {}", err);
+		process::exit(1);
+	}
 }
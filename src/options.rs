@@ -0,0 +1,582 @@
+#[cfg(feature = "serde")]
+use std::path::PathBuf;
+use column;
+use column::Column;
+use filter;
+use parse_block_type;
+use BlockType;
+
+/// When to highlight the output with ANSI escapes.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Default)]
+#[derive(PartialEq)]
+pub enum ColorMode {
+	/// Only when stdout is a terminal.
+	#[default]
+	Auto,
+	Always,
+	Never,
+}
+
+fn parse_color_mode(when : &str) -> Result<ColorMode, String> {
+	match when {
+		"auto" => Ok(ColorMode::Auto),
+		"always" => Ok(ColorMode::Always),
+		"never" => Ok(ColorMode::Never),
+		_ => Err(format!("invalid color mode '{}' (valid modes: auto, always, never)", when)),
+	}
+}
+
+#[derive(Default)]
+pub struct Options {
+	pub json : bool,
+	/// Print each top-level device as one compact JSON object per line.
+	pub json_lines : bool,
+	/// With `json`, list every device in one array, naming its parent.
+	pub json_flat : bool,
+	pub bytes : bool,
+	/// Show RM and RO as `yes` or `no` in the tree and list output.
+	pub verbose_flags : bool,
+	pub si : bool,
+	pub fs : bool,
+	pub nodeps : bool,
+	pub ascii : bool,
+	/// Draw the partition of a disk with only one without a branch.
+	pub compact_single_partition : bool,
+	pub noheadings : bool,
+	pub pairs : bool,
+	pub list : bool,
+	/// Print the tree upside down, from filesystems down to disks.
+	pub inverse : bool,
+	/// With `inverse`, hide the devices that devices of this type are built
+	/// on.
+	pub exclude_slaves : Option<BlockType>,
+	/// End each device's record with a NUL rather than a newline.
+	pub zero : bool,
+	pub mounted : bool,
+	pub removable_only : bool,
+	pub hide_empty : bool,
+	/// Hide RAM disks, loop devices, device-mapper devices and zram.
+	pub exclude_pseudo : bool,
+	/// Hide disks with a size of zero and no partitions, such as empty
+	/// card reader slots.
+	pub exclude_zero_size : bool,
+	/// Page the output through `$PAGER` when writing to a terminal.
+	pub pager : bool,
+	/// Read superblocks when udev has no data for a device.
+	pub probe : bool,
+	pub color : ColorMode,
+	pub columns : Option<Vec<Column>>,
+	/// Columns to print after the others, from `-o +COLUMN,...`.
+	pub extra_columns : Vec<Column>,
+	pub exclude : Option<Vec<u32>>,
+	pub include : Option<Vec<u32>>,
+	/// Show only devices with one of these filesystem types.
+	pub fstypes : Option<Vec<String>>,
+	/// Hide devices with one of these filesystem types.
+	pub exclude_fstypes : Option<Vec<String>>,
+	/// The columns to sort by, each breaking ties in the one before.
+	pub sort : Option<Vec<Column>>,
+	/// Sort in descending order.
+	pub reverse : bool,
+	/// Hide devices whose value in this column was already printed.
+	pub dedup : Option<Column>,
+	/// Move top-level devices with the same value in this column next to
+	/// each other.
+	pub group_by : Option<Column>,
+	/// Print a blank line between the groups made by `group_by`.
+	pub group_separator : bool,
+	/// The column to draw the tree in, instead of NAME.
+	pub tree : Option<Column>,
+	/// How many levels of the tree to show below the top.
+	pub tree_depth : Option<usize>,
+	/// Print the number of disks and their total and mounted capacity after
+	/// the devices.
+	pub summary : bool,
+	/// The output width, instead of the terminal's.
+	pub width : Option<usize>,
+	/// Scan and print again every this many seconds.
+	pub watch : Option<u64>,
+	/// Print a line as each device is added or removed.
+	pub monitor : bool,
+	/// Save the devices to this file instead of printing them.
+	#[cfg(feature = "serde")]
+	pub snapshot : Option<PathBuf>,
+	/// Print how the devices differ from those saved in this file.
+	#[cfg(feature = "serde")]
+	pub diff : Option<PathBuf>,
+	/// The device nodes to print, with everything built on them, instead of
+	/// every device.
+	pub devices : Vec<String>,
+}
+
+/// Short options that take a value, which may be attached (`-oNAME`) or
+/// given as the next argument (`-o NAME`).
+const SHORT_OPTIONS_WITH_VALUE : &str = "eIowx";
+
+/// Splits grouped short options (`-bJ`) and `--long=value` arguments so that
+/// every option and value is its own argument.
+fn split_args<I: Iterator<Item = String>>(args : I) -> Vec<String> {
+	let mut split = Vec::new();
+	for arg in args {
+		if arg.starts_with("--") {
+			match arg.find('=') {
+				Some(eq) => {
+					split.push(arg[..eq].to_owned());
+					split.push(arg[eq+1..].to_owned());
+				},
+				None => split.push(arg),
+			}
+		} else if arg.starts_with('-') && arg.len() > 2 {
+			for (i, c) in arg[1..].char_indices() {
+				split.push(format!("-{}", c));
+				if SHORT_OPTIONS_WITH_VALUE.contains(c) {
+					let value = &arg[1+i+c.len_utf8()..];
+					if !value.is_empty() {
+						split.push(value.to_owned());
+					}
+					break;
+				}
+			}
+		} else {
+			split.push(arg);
+		}
+	}
+	split
+}
+
+#[test]
+fn test_split_args() {
+	let split = |args : &[&str]| split_args(args.iter().map(|arg| arg.to_string()));
+	assert!(split(&["-bJ"]) == ["-b", "-J"]);
+	assert!(split(&["-bo", "NAME"]) == ["-b", "-o", "NAME"]);
+	assert!(split(&["-boNAME,SIZE"]) == ["-b", "-o", "NAME,SIZE"]);
+	assert!(split(&["--output=NAME"]) == ["--output", "NAME"]);
+	assert!(split(&["--output", "NAME"]) == ["--output", "NAME"]);
+}
+
+fn option_value<I: Iterator<Item = String>>(option : &str, args : &mut I) -> Result<String, String> {
+	args.next().ok_or_else(|| format!("option '{}' requires an argument", option))
+}
+
+fn parse_width(width : &str) -> Result<usize, String> {
+	match width.parse::<usize>() {
+		Ok(width) if width > 0 => Ok(width),
+		_ => Err(format!("invalid width '{}'", width)),
+	}
+}
+
+fn parse_interval(seconds : &str) -> Result<u64, String> {
+	match seconds.parse::<u64>() {
+		Ok(seconds) if seconds > 0 => Ok(seconds),
+		_ => Err(format!("invalid interval '{}'", seconds)),
+	}
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(args : I) -> Result<Options, String> {
+	let mut opts = Options::default();
+	let mut args = split_args(args).into_iter();
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-J" | "--json" => opts.json = true,
+			"--json-lines" => opts.json_lines = true,
+			"--json-flat" => {
+				opts.json = true;
+				opts.json_flat = true;
+			},
+			"-b" | "--bytes" => opts.bytes = true,
+			"--verbose-flags" => opts.verbose_flags = true,
+			"--si" => opts.si = true,
+			"-f" | "--fs" => opts.fs = true,
+			"-d" | "--nodeps" => opts.nodeps = true,
+			"-i" | "--ascii" => opts.ascii = true,
+			"--no-tree-for-single-partition" => opts.compact_single_partition = true,
+			"-n" | "--noheadings" => opts.noheadings = true,
+			"-P" | "--pairs" => opts.pairs = true,
+			"--pairs-with-raw" => {
+				opts.pairs = true;
+				opts.bytes = true;
+			},
+			"-l" | "--list" => opts.list = true,
+			"-s" | "--inverse" => opts.inverse = true,
+			"--exclude-slaves" => {
+				let name = option_value(&arg, &mut args)?;
+				opts.exclude_slaves = Some(parse_block_type(&name)?);
+			},
+			"-z" | "--raw-zero" => opts.zero = true,
+			"--mounted" => opts.mounted = true,
+			"--removable-only" => opts.removable_only = true,
+			"--hide-empty" => opts.hide_empty = true,
+			"--exclude-zero-size" => opts.exclude_zero_size = true,
+			"--real" | "--exclude-pseudo" => opts.exclude_pseudo = true,
+			"--pager" => opts.pager = true,
+			"--probe" => opts.probe = true,
+			"--summary" => opts.summary = true,
+			"--color" => {
+				let when = option_value(&arg, &mut args)?;
+				opts.color = parse_color_mode(&when)?;
+			},
+			"-o" | "--output" => {
+				let list = option_value(&arg, &mut args)?;
+				match list.strip_prefix('+') {
+					Some(list) => opts.extra_columns.extend(column::parse_columns(list)?),
+					None => opts.columns = Some(column::parse_columns(&list)?),
+				}
+			},
+			"-O" | "--output-all" => opts.columns = Some(column::ALL_COLUMNS.to_vec()),
+			"-x" | "--sort" => {
+				let list = option_value(&arg, &mut args)?;
+				opts.sort = Some(column::parse_columns(&list)?);
+			},
+			"--reverse" => opts.reverse = true,
+			"--tree-depth" => {
+				let depth = option_value(&arg, &mut args)?;
+				opts.tree_depth = Some(depth.parse::<usize>().map_err(|_| format!("invalid depth '{}'", depth))?);
+			},
+			"--dedup" => {
+				let name = option_value(&arg, &mut args)?;
+				opts.dedup = Some(name.parse::<Column>()?);
+			},
+			"--group-by" => {
+				let name = option_value(&arg, &mut args)?;
+				opts.group_by = Some(name.parse::<Column>()?);
+			},
+			"--group-separator" => opts.group_separator = true,
+			"--tree" => {
+				let name = option_value(&arg, &mut args)?;
+				opts.tree = Some(name.parse::<Column>()?);
+			},
+			"-w" | "--width" => {
+				let width = option_value(&arg, &mut args)?;
+				opts.width = Some(parse_width(&width)?);
+			},
+			"--monitor" => opts.monitor = true,
+			#[cfg(feature = "serde")]
+			"--snapshot" => opts.snapshot = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+			#[cfg(feature = "serde")]
+			"--diff" => opts.diff = Some(PathBuf::from(option_value(&arg, &mut args)?)),
+			"--watch" => {
+				let seconds = option_value(&arg, &mut args)?;
+				opts.watch = Some(parse_interval(&seconds)?);
+			},
+			"-e" | "--exclude" => {
+				let list = option_value(&arg, &mut args)?;
+				opts.exclude = Some(filter::parse_majors(&list)?);
+			},
+			"-I" | "--include" => {
+				let list = option_value(&arg, &mut args)?;
+				opts.include = Some(filter::parse_majors(&list)?);
+			},
+			"--fstype" => {
+				let list = option_value(&arg, &mut args)?;
+				opts.fstypes = Some(filter::parse_fstypes(&list));
+			},
+			"--exclude-fstype" => {
+				let list = option_value(&arg, &mut args)?;
+				opts.exclude_fstypes = Some(filter::parse_fstypes(&list));
+			},
+			_ if !arg.starts_with('-') => opts.devices.push(arg),
+			_ => return Err(format!("unrecognized option '{}'", arg)),
+		}
+	}
+	if [opts.json, opts.json_lines, opts.pairs, opts.list].iter().filter(|&&mode| mode).count() > 1 {
+		return Err("--json, --json-lines, --pairs and --list are mutually exclusive".to_owned());
+	}
+	if opts.zero && (opts.json || opts.json_lines) {
+		return Err("--raw-zero cannot be combined with --json or --json-lines".to_owned());
+	}
+	if opts.tree.is_some() && (opts.json || opts.json_lines || opts.pairs || opts.list) {
+		return Err("--tree cannot be combined with --json, --json-lines, --pairs or --list".to_owned());
+	}
+	if opts.summary && (opts.json || opts.json_lines || opts.pairs || opts.watch.is_some()) {
+		return Err("--summary cannot be combined with --json, --json-lines, --pairs or --watch".to_owned());
+	}
+	if opts.exclude_slaves.is_some() && !opts.inverse {
+		return Err("--exclude-slaves requires --inverse".to_owned());
+	}
+	if opts.group_separator && opts.group_by.is_none() {
+		return Err("--group-separator requires --group-by".to_owned());
+	}
+	if opts.monitor && opts.watch.is_some() {
+		return Err("--monitor cannot be combined with --watch".to_owned());
+	}
+	#[cfg(feature = "serde")]
+	{
+		if opts.snapshot.is_some() && opts.diff.is_some() {
+			return Err("--snapshot and --diff are mutually exclusive".to_owned());
+		}
+		if (opts.snapshot.is_some() || opts.diff.is_some()) && (opts.monitor || opts.watch.is_some()) {
+			return Err("--snapshot and --diff cannot be combined with --monitor or --watch".to_owned());
+		}
+	}
+	if opts.watch.is_some() && opts.pager {
+		return Err("--watch cannot be combined with --pager".to_owned());
+	}
+	if opts.include.is_some() && opts.exclude.is_some() {
+		return Err("--include and --exclude are mutually exclusive".to_owned());
+	}
+	Ok(opts)
+}
+
+#[cfg(test)]
+fn parse_test_args(args : &[&str]) -> Result<Options, String> {
+	parse_args(args.iter().map(|arg| arg.to_string()))
+}
+
+#[test]
+fn test_parse_args() {
+	assert!(!parse_test_args(&[]).unwrap().json);
+	assert!(parse_test_args(&["-J"]).unwrap().json);
+	assert!(parse_test_args(&["--json"]).unwrap().json);
+	assert!(!parse_test_args(&[]).unwrap().json_lines);
+	assert!(parse_test_args(&["--json-lines"]).unwrap().json_lines);
+	assert!(!parse_test_args(&["-J"]).unwrap().json_flat);
+	assert!(parse_test_args(&["--json-flat"]).unwrap().json);
+	assert!(parse_test_args(&["--json-flat"]).unwrap().json_flat);
+	assert!(parse_test_args(&["--json-flat", "-P"]).is_err());
+	assert!(parse_test_args(&["--json-lines", "-J"]).err() == Some("--json, --json-lines, --pairs and --list are mutually exclusive".to_owned()));
+	assert!(parse_test_args(&["-b"]).unwrap().bytes);
+	assert!(parse_test_args(&["--bytes"]).unwrap().bytes);
+	assert!(!parse_test_args(&[]).unwrap().verbose_flags);
+	assert!(parse_test_args(&["--verbose-flags"]).unwrap().verbose_flags);
+	assert!(!parse_test_args(&[]).unwrap().si);
+	assert!(parse_test_args(&["--si"]).unwrap().si);
+	assert!(parse_test_args(&["-f"]).unwrap().fs);
+	assert!(parse_test_args(&["-d"]).unwrap().nodeps);
+	assert!(parse_test_args(&["--nodeps"]).unwrap().nodeps);
+	assert!(parse_test_args(&["-i"]).unwrap().ascii);
+	assert!(parse_test_args(&["--ascii"]).unwrap().ascii);
+	assert!(!parse_test_args(&[]).unwrap().compact_single_partition);
+	assert!(parse_test_args(&["--no-tree-for-single-partition"]).unwrap().compact_single_partition);
+	assert!(parse_test_args(&["-n"]).unwrap().noheadings);
+	assert!(parse_test_args(&["--noheadings"]).unwrap().noheadings);
+	assert!(parse_test_args(&["-P"]).unwrap().pairs);
+	assert!(parse_test_args(&["--pairs"]).unwrap().pairs);
+	assert!(!parse_test_args(&["--pairs"]).unwrap().bytes);
+	assert!(parse_test_args(&["--pairs-with-raw"]).unwrap().pairs);
+	assert!(parse_test_args(&["--pairs-with-raw"]).unwrap().bytes);
+	assert!(parse_test_args(&["-l"]).unwrap().list);
+	assert!(parse_test_args(&["--list"]).unwrap().list);
+	assert!(parse_test_args(&["-z"]).unwrap().zero);
+	assert!(parse_test_args(&["-Pz"]).unwrap().zero);
+	assert!(parse_test_args(&["--raw-zero", "-J"]).err() == Some("--raw-zero cannot be combined with --json or --json-lines".to_owned()));
+	assert!(parse_test_args(&["-P", "-J"]).err() == Some("--json, --json-lines, --pairs and --list are mutually exclusive".to_owned()));
+	assert!(parse_test_args(&["-l", "-P"]).is_err());
+	assert!(parse_test_args(&["-lJ"]).is_err());
+	assert!(parse_test_args(&["--jsn"]).is_err());
+}
+
+#[test]
+fn test_parse_args_output() {
+	assert!(parse_test_args(&[]).unwrap().columns.is_none());
+	assert!(parse_test_args(&["-o", "NAME,SIZE"]).unwrap().columns == Some(vec![Column::Name, Column::Size]));
+	assert!(parse_test_args(&["--output=size,name"]).unwrap().columns == Some(vec![Column::Size, Column::Name]));
+	assert!(parse_test_args(&["-o"]).err() == Some("option '-o' requires an argument".to_owned()));
+	assert!(parse_test_args(&["-o", "NAME,NOPE"]).is_err());
+	assert!(parse_test_args(&["-O"]).unwrap().columns == Some(column::ALL_COLUMNS.to_vec()));
+	assert!(parse_test_args(&["-o", "NAME", "--output-all"]).unwrap().columns == Some(column::ALL_COLUMNS.to_vec()));
+	let opts = parse_test_args(&["-o", "+UUID", "-o+label,MODEL"]).unwrap();
+	assert!(opts.columns.is_none());
+	assert!(opts.extra_columns == [Column::Uuid, Column::Label, Column::Model]);
+	assert!(parse_test_args(&["-o", "+"]).is_err());
+}
+
+#[test]
+fn test_parse_args_sort() {
+	assert!(parse_test_args(&[]).unwrap().sort.is_none());
+	assert!(parse_test_args(&["-x", "SIZE"]).unwrap().sort == Some(vec![Column::Size]));
+	assert!(parse_test_args(&["--sort=maj:min"]).unwrap().sort == Some(vec![Column::MajMin]));
+	assert!(parse_test_args(&["-x", "TYPE,SIZE"]).unwrap().sort == Some(vec![Column::Type, Column::Size]));
+	assert!(parse_test_args(&["-x", "NOPE"]).is_err());
+	assert!(parse_test_args(&["-x", "SIZE,"]).is_err());
+	assert!(!parse_test_args(&[]).unwrap().reverse);
+	assert!(parse_test_args(&["-x", "SIZE", "--reverse"]).unwrap().reverse);
+}
+
+#[test]
+fn test_parse_args_tree_depth() {
+	assert!(parse_test_args(&[]).unwrap().tree_depth.is_none());
+	assert!(parse_test_args(&["--tree-depth", "1"]).unwrap().tree_depth == Some(1));
+	assert!(parse_test_args(&["--tree-depth=0"]).unwrap().tree_depth == Some(0));
+	assert!(parse_test_args(&["--tree-depth", "deep"]).err() == Some("invalid depth 'deep'".to_owned()));
+	assert!(parse_test_args(&["--tree-depth", "-1"]).err() == Some("invalid depth '-1'".to_owned()));
+}
+
+#[test]
+fn test_parse_args_dedup() {
+	assert!(parse_test_args(&[]).unwrap().dedup.is_none());
+	assert!(parse_test_args(&["--dedup", "UUID"]).unwrap().dedup == Some(Column::Uuid));
+	assert!(parse_test_args(&["--dedup=name"]).unwrap().dedup == Some(Column::Name));
+	assert!(parse_test_args(&["--dedup", "NOPE"]).is_err());
+}
+
+#[test]
+fn test_parse_args_group_by() {
+	let opts = parse_test_args(&[]).unwrap();
+	assert!(opts.group_by.is_none());
+	assert!(!opts.group_separator);
+	assert!(parse_test_args(&["--group-by", "WWN"]).unwrap().group_by == Some(Column::Wwn));
+	assert!(parse_test_args(&["--group-by=model", "--group-separator"]).unwrap().group_separator);
+	assert!(parse_test_args(&["--group-by", "NOPE"]).is_err());
+	assert!(parse_test_args(&["--group-separator"]).err() == Some("--group-separator requires --group-by".to_owned()));
+}
+
+#[test]
+fn test_parse_args_tree() {
+	assert!(parse_test_args(&[]).unwrap().tree.is_none());
+	assert!(parse_test_args(&["--tree=MOUNTPOINT"]).unwrap().tree == Some(Column::Mountpoint));
+	assert!(parse_test_args(&["--tree", "type"]).unwrap().tree == Some(Column::Type));
+	assert!(parse_test_args(&["--tree"]).is_err());
+	assert!(parse_test_args(&["--tree=NAME", "-l"]).err() ==
+		Some("--tree cannot be combined with --json, --json-lines, --pairs or --list".to_owned()));
+}
+
+#[test]
+fn test_parse_args_summary() {
+	assert!(!parse_test_args(&[]).unwrap().summary);
+	assert!(parse_test_args(&["--summary"]).unwrap().summary);
+	assert!(parse_test_args(&["--summary", "-l"]).unwrap().summary);
+	assert!(parse_test_args(&["--summary", "-P"]).err() ==
+		Some("--summary cannot be combined with --json, --json-lines, --pairs or --watch".to_owned()));
+	assert!(parse_test_args(&["--summary", "--watch", "2"]).is_err());
+}
+
+#[test]
+fn test_parse_args_color() {
+	assert!(parse_test_args(&[]).unwrap().color == ColorMode::Auto);
+	assert!(parse_test_args(&["--color=always"]).unwrap().color == ColorMode::Always);
+	assert!(parse_test_args(&["--color", "never"]).unwrap().color == ColorMode::Never);
+	assert!(parse_test_args(&["--color=auto"]).unwrap().color == ColorMode::Auto);
+	assert!(parse_test_args(&["--color=sometimes"]).err() ==
+		Some("invalid color mode 'sometimes' (valid modes: auto, always, never)".to_owned()));
+}
+
+#[test]
+fn test_parse_args_exclude() {
+	assert!(parse_test_args(&[]).unwrap().exclude.is_none());
+	assert!(parse_test_args(&["-e", "7,1"]).unwrap().exclude == Some(vec![7, 1]));
+	assert!(parse_test_args(&["-e7"]).unwrap().exclude == Some(vec![7]));
+	assert!(parse_test_args(&["--exclude=253"]).unwrap().exclude == Some(vec![253]));
+	assert!(parse_test_args(&["-e", "loop"]).is_err());
+}
+
+#[test]
+fn test_parse_args_mounted() {
+	assert!(!parse_test_args(&[]).unwrap().mounted);
+	assert!(parse_test_args(&["--mounted"]).unwrap().mounted);
+}
+
+#[test]
+fn test_parse_args_removable_only() {
+	assert!(!parse_test_args(&[]).unwrap().removable_only);
+	assert!(parse_test_args(&["--removable-only"]).unwrap().removable_only);
+}
+
+#[test]
+fn test_parse_args_hide_empty() {
+	assert!(!parse_test_args(&[]).unwrap().hide_empty);
+	assert!(parse_test_args(&["--hide-empty"]).unwrap().hide_empty);
+	assert!(!parse_test_args(&[]).unwrap().exclude_zero_size);
+	assert!(parse_test_args(&["--exclude-zero-size"]).unwrap().exclude_zero_size);
+	assert!(!parse_test_args(&[]).unwrap().exclude_pseudo);
+	assert!(parse_test_args(&["--real"]).unwrap().exclude_pseudo);
+	assert!(parse_test_args(&["--exclude-pseudo"]).unwrap().exclude_pseudo);
+}
+
+#[test]
+fn test_parse_args_width() {
+	assert!(parse_test_args(&[]).unwrap().width.is_none());
+	assert!(parse_test_args(&["-w", "80"]).unwrap().width == Some(80));
+	assert!(parse_test_args(&["-w120"]).unwrap().width == Some(120));
+	assert!(parse_test_args(&["--width=40"]).unwrap().width == Some(40));
+	assert!(parse_test_args(&["--width", "0"]).err() == Some("invalid width '0'".to_owned()));
+	assert!(parse_test_args(&["-w", "wide"]).err() == Some("invalid width 'wide'".to_owned()));
+}
+
+#[test]
+fn test_parse_args_inverse() {
+	assert!(!parse_test_args(&[]).unwrap().inverse);
+	assert!(parse_test_args(&["-s"]).unwrap().inverse);
+	assert!(parse_test_args(&["--inverse"]).unwrap().inverse);
+	assert!(parse_test_args(&[]).unwrap().exclude_slaves.is_none());
+	assert!(parse_test_args(&["-s", "--exclude-slaves", "dm"]).unwrap().exclude_slaves == Some(BlockType::DeviceMapper));
+	assert!(parse_test_args(&["-s", "--exclude-slaves=lvm"]).is_err());
+	assert!(parse_test_args(&["--exclude-slaves", "crypt"]).err() == Some("--exclude-slaves requires --inverse".to_owned()));
+}
+
+#[test]
+fn test_parse_args_fstype() {
+	let opts = parse_test_args(&[]).unwrap();
+	assert!(opts.fstypes.is_none());
+	assert!(opts.exclude_fstypes.is_none());
+	assert!(parse_test_args(&["--fstype", "ext4,xfs"]).unwrap().fstypes == Some(vec!["ext4".to_owned(), "xfs".to_owned()]));
+	assert!(parse_test_args(&["--exclude-fstype=swap"]).unwrap().exclude_fstypes == Some(vec!["swap".to_owned()]));
+	assert!(parse_test_args(&["--fstype"]).err() == Some("option '--fstype' requires an argument".to_owned()));
+}
+
+#[test]
+fn test_parse_args_probe() {
+	assert!(!parse_test_args(&[]).unwrap().probe);
+	assert!(parse_test_args(&["--probe"]).unwrap().probe);
+}
+
+#[test]
+fn test_parse_args_pager() {
+	assert!(!parse_test_args(&[]).unwrap().pager);
+	assert!(parse_test_args(&["--pager"]).unwrap().pager);
+}
+
+#[test]
+fn test_parse_args_watch() {
+	assert!(parse_test_args(&[]).unwrap().watch.is_none());
+	assert!(parse_test_args(&["--watch", "2"]).unwrap().watch == Some(2));
+	assert!(parse_test_args(&["--watch=10"]).unwrap().watch == Some(10));
+	assert!(parse_test_args(&["--watch", "0"]).err() == Some("invalid interval '0'".to_owned()));
+	assert!(parse_test_args(&["--watch", "soon"]).err() == Some("invalid interval 'soon'".to_owned()));
+	assert!(parse_test_args(&["--watch", "2", "--pager"]).err() == Some("--watch cannot be combined with --pager".to_owned()));
+}
+
+#[test]
+fn test_parse_args_monitor() {
+	assert!(!parse_test_args(&[]).unwrap().monitor);
+	assert!(parse_test_args(&["--monitor"]).unwrap().monitor);
+	assert!(parse_test_args(&["--monitor", "--watch", "2"]).err() == Some("--monitor cannot be combined with --watch".to_owned()));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_parse_args_snapshot() {
+	let opts = parse_test_args(&[]).unwrap();
+	assert!(opts.snapshot.is_none() && opts.diff.is_none());
+	assert!(parse_test_args(&["--snapshot", "before.json"]).unwrap().snapshot == Some(PathBuf::from("before.json")));
+	assert!(parse_test_args(&["--diff=before.json"]).unwrap().diff == Some(PathBuf::from("before.json")));
+	assert!(parse_test_args(&["--snapshot", "a.json", "--diff", "b.json"]).err() ==
+		Some("--snapshot and --diff are mutually exclusive".to_owned()));
+	assert!(parse_test_args(&["--diff", "a.json", "--watch", "2"]).is_err());
+}
+
+#[cfg(not(feature = "serde"))]
+#[test]
+fn test_parse_args_snapshot_without_serde() {
+	assert!(parse_test_args(&["--snapshot", "before.json"]).err() == Some("unrecognized option '--snapshot'".to_owned()));
+	assert!(parse_test_args(&["--diff", "before.json"]).err() == Some("unrecognized option '--diff'".to_owned()));
+}
+
+#[test]
+fn test_parse_args_devices() {
+	assert!(parse_test_args(&[]).unwrap().devices.is_empty());
+	let opts = parse_test_args(&["/dev/sda", "-o", "NAME", "/dev/nvme0n1p1"]).unwrap();
+	assert!(opts.devices == ["/dev/sda", "/dev/nvme0n1p1"]);
+	assert!(opts.columns == Some(vec![Column::Name]));
+	assert!(parse_test_args(&["-q"]).err() == Some("unrecognized option '-q'".to_owned()));
+}
+
+#[test]
+fn test_parse_args_include() {
+	assert!(parse_test_args(&[]).unwrap().include.is_none());
+	assert!(parse_test_args(&["-I", "8,259"]).unwrap().include == Some(vec![8, 259]));
+	assert!(parse_test_args(&["--include=8"]).unwrap().include == Some(vec![8]));
+	assert!(parse_test_args(&["-I", "8", "-e", "7"]).err() ==
+		Some("--include and --exclude are mutually exclusive".to_owned()));
+}
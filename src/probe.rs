@@ -0,0 +1,203 @@
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+
+/// The filesystem found on a device by `probe`.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Probe {
+	pub fs_type : &'static str,
+	pub uuid : Option<String>,
+}
+
+fn read_at<R: Read + Seek>(dev : &mut R, offset : u64, len : usize) -> Option<Vec<u8>> {
+	let mut buf = vec![0; len];
+	dev.seek(SeekFrom::Start(offset)).ok()?;
+	dev.read_exact(&mut buf).ok()?;
+	Some(buf)
+}
+
+fn le_u16(buf : &[u8], offset : usize) -> u16 {
+	u16::from(buf[offset]) | u16::from(buf[offset + 1]) << 8
+}
+
+fn le_u32(buf : &[u8], offset : usize) -> u32 {
+	u32::from(le_u16(buf, offset)) | u32::from(le_u16(buf, offset + 2)) << 16
+}
+
+/// Formats 16 raw bytes as a UUID, e.g. `c9a1b8a2-5d0e-4b3b-9f34-1e2d3c4b5a69`.
+fn format_uuid(bytes : &[u8]) -> String {
+	let hex = bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>();
+	format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+}
+
+#[test]
+fn test_format_uuid() {
+	let bytes = [0xc9, 0xa1, 0xb8, 0xa2, 0x5d, 0x0e, 0x4b, 0x3b, 0x9f, 0x34, 0x1e, 0x2d, 0x3c, 0x4b, 0x5a, 0x69];
+	assert!(format_uuid(&bytes) == "c9a1b8a2-5d0e-4b3b-9f34-1e2d3c4b5a69");
+}
+
+const EXT_SUPERBLOCK : u64 = 1024;
+const EXT_MAGIC : u16 = 0xef53;
+const EXT3_FEATURE_COMPAT_HAS_JOURNAL : u32 = 0x0004;
+/// The incompatible features that ext3 also understands: filetype, needs
+/// recovery and meta_bg.
+const EXT3_FEATURE_INCOMPAT_SUPP : u32 = 0x0002 | 0x0004 | 0x0010;
+/// The read-only features that ext3 also understands: sparse_super,
+/// large_file and btree_dir.
+const EXT3_FEATURE_RO_COMPAT_SUPP : u32 = 0x0001 | 0x0002 | 0x0004;
+
+/// ext2, ext3 and ext4 share a superblock, and are told apart by their
+/// feature flags in the same way as blkid: anything ext3 cannot mount is
+/// ext4, and a journal makes ext3.
+fn probe_ext<R: Read + Seek>(dev : &mut R) -> Option<Probe> {
+	let sb = read_at(dev, EXT_SUPERBLOCK, 0x78)?;
+	if le_u16(&sb, 0x38) != EXT_MAGIC {
+		return None;
+	}
+	let compat = le_u32(&sb, 0x5c);
+	let incompat = le_u32(&sb, 0x60);
+	let ro_compat = le_u32(&sb, 0x64);
+	let fs_type = if incompat & !EXT3_FEATURE_INCOMPAT_SUPP != 0 || ro_compat & !EXT3_FEATURE_RO_COMPAT_SUPP != 0 {
+		"ext4"
+	} else if compat & EXT3_FEATURE_COMPAT_HAS_JOURNAL != 0 {
+		"ext3"
+	} else {
+		"ext2"
+	};
+	Some(Probe { fs_type, uuid: Some(format_uuid(&sb[0x68..0x78])) })
+}
+
+/// FAT12 and FAT16 keep the filesystem type and volume ID at one offset in
+/// the boot sector, and FAT32 at another.
+fn probe_vfat<R: Read + Seek>(dev : &mut R) -> Option<Probe> {
+	let bs = read_at(dev, 0, 512)?;
+	if bs[510..512] != [0x55, 0xaa] {
+		return None;
+	}
+	let volume_id = if &bs[82..87] == b"FAT32" {
+		le_u32(&bs, 67)
+	} else if &bs[54..59] == b"FAT12" || &bs[54..59] == b"FAT16" {
+		le_u32(&bs, 39)
+	} else {
+		return None;
+	};
+	Some(Probe { fs_type: "vfat", uuid: Some(format!("{:04X}-{:04X}", volume_id >> 16, volume_id & 0xffff)) })
+}
+
+fn probe_xfs<R: Read + Seek>(dev : &mut R) -> Option<Probe> {
+	let sb = read_at(dev, 0, 48)?;
+	if &sb[0..4] != b"XFSB" {
+		return None;
+	}
+	Some(Probe { fs_type: "xfs", uuid: Some(format_uuid(&sb[32..48])) })
+}
+
+const BTRFS_SUPERBLOCK : u64 = 65536;
+
+fn probe_btrfs<R: Read + Seek>(dev : &mut R) -> Option<Probe> {
+	let sb = read_at(dev, BTRFS_SUPERBLOCK, 72)?;
+	if &sb[64..72] != b"_BHRfS_M" {
+		return None;
+	}
+	Some(Probe { fs_type: "btrfs", uuid: Some(format_uuid(&sb[32..48])) })
+}
+
+/// The page sizes that a swap signature may be written for. The signature
+/// sits in the last bytes of the first page.
+const SWAP_PAGE_SIZES : &[u64] = &[4096, 8192, 16384, 65536];
+
+fn probe_swap<R: Read + Seek>(dev : &mut R) -> Option<Probe> {
+	for &page_size in SWAP_PAGE_SIZES {
+		match read_at(dev, page_size - 10, 10) {
+			Some(ref magic) if magic == b"SWAPSPACE2" => {
+				let uuid = read_at(dev, 1024 + 12, 16).map(|uuid| format_uuid(&uuid));
+				return Some(Probe { fs_type: "swap", uuid });
+			},
+			Some(ref magic) if magic == b"SWAP-SPACE" => return Some(Probe { fs_type: "swap", uuid: None }),
+			_ => (),
+		}
+	}
+	None
+}
+
+/// Looks for an ext2/3/4, vfat, xfs, btrfs or swap signature at the start
+/// of `dev`.
+pub fn probe<R: Read + Seek>(dev : &mut R) -> Option<Probe> {
+	probe_ext(dev)
+		.or_else(|| probe_xfs(dev))
+		.or_else(|| probe_btrfs(dev))
+		.or_else(|| probe_swap(dev))
+		.or_else(|| probe_vfat(dev))
+}
+
+#[cfg(test)]
+fn ext4_image() -> Vec<u8> {
+	let mut image = vec![0; 4096];
+	let sb = EXT_SUPERBLOCK as usize;
+	image[sb + 0x38] = 0x53;
+	image[sb + 0x39] = 0xef;
+	image[sb + 0x5c] = 0x3c;
+	// extent, 64bit and flex_bg
+	image[sb + 0x60] = 0xc2;
+	image[sb + 0x61] = 0x02;
+	image[sb + 0x68..sb + 0x78].copy_from_slice(&[0xc9, 0xa1, 0xb8, 0xa2, 0x5d, 0x0e, 0x4b, 0x3b, 0x9f, 0x34, 0x1e, 0x2d, 0x3c, 0x4b, 0x5a, 0x69]);
+	image
+}
+
+#[cfg(test)]
+fn vfat_image() -> Vec<u8> {
+	let mut image = vec![0; 4096];
+	image[0..3].copy_from_slice(&[0xeb, 0x58, 0x90]);
+	image[3..11].copy_from_slice(b"mkfs.fat");
+	image[67..71].copy_from_slice(&[0xef, 0xbe, 0x34, 0x12]);
+	image[82..90].copy_from_slice(b"FAT32   ");
+	image[510] = 0x55;
+	image[511] = 0xaa;
+	image
+}
+
+#[test]
+fn test_probe_ext() {
+	use std::io::Cursor;
+
+	let image = ext4_image();
+	assert!(probe(&mut Cursor::new(&image)) ==
+		Some(Probe { fs_type: "ext4", uuid: Some("c9a1b8a2-5d0e-4b3b-9f34-1e2d3c4b5a69".to_owned()) }));
+
+	let mut ext3 = image.clone();
+	ext3[EXT_SUPERBLOCK as usize + 0x60] = 0x02;
+	ext3[EXT_SUPERBLOCK as usize + 0x61] = 0x00;
+	assert!(probe(&mut Cursor::new(&ext3)).map(|probe| probe.fs_type) == Some("ext3"));
+
+	ext3[EXT_SUPERBLOCK as usize + 0x5c] = 0x38;
+	assert!(probe(&mut Cursor::new(&ext3)).map(|probe| probe.fs_type) == Some("ext2"));
+}
+
+#[test]
+fn test_probe_vfat() {
+	use std::io::Cursor;
+
+	let image = vfat_image();
+	assert!(probe(&mut Cursor::new(&image)) == Some(Probe { fs_type: "vfat", uuid: Some("1234-BEEF".to_owned()) }));
+
+	let mut unsigned = image.clone();
+	unsigned[510] = 0;
+	assert!(probe(&mut Cursor::new(&unsigned)).is_none());
+}
+
+#[test]
+fn test_probe_other() {
+	use std::io::Cursor;
+
+	let mut swap = vec![0; 4096];
+	swap[4086..4096].copy_from_slice(b"SWAPSPACE2");
+	assert!(probe(&mut Cursor::new(&swap)).map(|probe| probe.fs_type) == Some("swap"));
+
+	let mut xfs = vec![0; 512];
+	xfs[0..4].copy_from_slice(b"XFSB");
+	assert!(probe(&mut Cursor::new(&xfs)).map(|probe| probe.fs_type) == Some("xfs"));
+
+	assert!(probe(&mut Cursor::new(&vec![0; 4096])).is_none());
+	assert!(probe(&mut Cursor::new(&vec![0; 16])).is_none());
+}
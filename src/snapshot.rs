@@ -0,0 +1,138 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use lsblk::Block;
+use lsblk::MajorMinor;
+use column::CellFormat;
+use added_line;
+use block_rows;
+use find_row;
+use serde_json;
+
+/// Saves `blocks` to `path` as JSON, for a later `--diff`.
+pub fn write_snapshot(path : &Path, blocks : &[Block]) -> Result<(), String> {
+	let file = File::create(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+	serde_json::to_writer_pretty(io::BufWriter::new(file), blocks).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Reads the devices saved by `write_snapshot`.
+pub fn read_snapshot(path : &Path) -> Result<Vec<Block>, String> {
+	let file = File::open(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+	serde_json::from_reader(io::BufReader::new(file)).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// How a device differs between a snapshot and the live system.
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Change {
+	Added(String),
+	Removed(String),
+	Resized(String, Option<u64>, Option<u64>),
+	Remounted(String, String, String),
+}
+
+/// The parts of a disk or partition that `diff_blocks` compares.
+struct Device<'a> {
+	name : &'a str,
+	majmin : MajorMinor,
+	size : Option<u64>,
+	mountpoint : &'a str,
+}
+
+fn devices(blocks : &[Block]) -> Vec<Device<'_>> {
+	let mut devices = Vec::new();
+	for block in blocks {
+		devices.push(Device { name: &block.name, majmin: block.majmin, size: block.size, mountpoint: &block.mountpoint });
+		devices.extend(block.partitions.iter().map(|part| {
+			Device { name: &part.name, majmin: part.majmin, size: part.size, mountpoint: &part.mountpoint }
+		}));
+	}
+	devices
+}
+
+fn find_device<'a, 'b>(devices : &'a [Device<'b>], device : &Device) -> Option<&'a Device<'b>> {
+	devices.iter().find(|other| other.majmin == device.majmin && other.name == device.name)
+}
+
+/// Compares two scans device by device, matching them up by device number.
+/// A number that now belongs to a device of another name, such as a reused
+/// loop device, counts as one device removed and another added.
+pub fn diff_blocks(old : &[Block], new : &[Block]) -> Vec<Change> {
+	let old = devices(old);
+	let new = devices(new);
+	let mut changes = Vec::new();
+	for device in &old {
+		match find_device(&new, device) {
+			None => changes.push(Change::Removed(device.name.to_owned())),
+			Some(other) => {
+				if other.size != device.size {
+					changes.push(Change::Resized(device.name.to_owned(), device.size, other.size));
+				}
+				if other.mountpoint != device.mountpoint {
+					changes.push(Change::Remounted(device.name.to_owned(), device.mountpoint.to_owned(), other.mountpoint.to_owned()));
+				}
+			},
+		}
+	}
+	changes.extend(new.iter().filter(|device| find_device(&old, device).is_none()).map(|device| Change::Added(device.name.to_owned())));
+	changes
+}
+
+#[test]
+fn test_diff_blocks() {
+	use test_block;
+
+	let old = vec![test_block()];
+	assert!(diff_blocks(&old, &old).is_empty());
+
+	let mut new = old.clone();
+	new[0].partitions.pop();
+	assert!(diff_blocks(&old, &new) == [Change::Removed("sda2".to_owned())]);
+	assert!(diff_blocks(&new, &old) == [Change::Added("sda2".to_owned())]);
+
+	let mut new = old.clone();
+	new[0].partitions[1].size = Some(68719476736);
+	new[0].partitions[0].mountpoint = String::new();
+	assert!(diff_blocks(&old, &new) == [
+		Change::Remounted("sda1".to_owned(), "/boot".to_owned(), String::new()),
+		Change::Resized("sda2".to_owned(), Some(34299674624), Some(68719476736)),
+	]);
+
+	let mut new = old.clone();
+	new[0].partitions[1].name = "sdb1".to_owned();
+	assert!(diff_blocks(&old, &new) == [Change::Removed("sda2".to_owned()), Change::Added("sdb1".to_owned())]);
+}
+
+/// A line describing `change`, in the style of `--monitor`: `+sdb1 ext4
+/// 7.5G part` for a device that appeared, `-sdb1` for one that went away
+/// and `~sda2 size 16G -> 32G` for one that changed. `new` is the live scan,
+/// where added devices are looked up.
+pub fn change_line(change : &Change, new : &[Block], format : &CellFormat) -> String {
+	let mountpoint = |mountpoint : &str| if mountpoint.is_empty() { "(none)".to_owned() } else { mountpoint.to_owned() };
+	match *change {
+		Change::Added(ref name) => {
+			let rows = block_rows(new, false);
+			find_row(&rows, name).map_or_else(|| format!("+{}", name), added_line)
+		},
+		Change::Removed(ref name) => format!("-{}", name),
+		Change::Resized(ref name, old, new) =>
+			format!("~{} size {} -> {}", name, format.size(old).trim(), format.size(new).trim()),
+		Change::Remounted(ref name, ref old, ref new) =>
+			format!("~{} mountpoint {} -> {}", name, mountpoint(old), mountpoint(new)),
+	}
+}
+
+#[test]
+fn test_change_line() {
+	use test_block;
+
+	let blocks = vec![test_block()];
+	let format = CellFormat::default();
+	assert!(change_line(&Change::Added("sda1".to_owned()), &blocks, &format) == "+sda1 vfat 57M part");
+	assert!(change_line(&Change::Added("sdb".to_owned()), &blocks, &format) == "+sdb");
+	assert!(change_line(&Change::Removed("sdb1".to_owned()), &blocks, &format) == "-sdb1");
+	assert!(change_line(&Change::Resized("sda2".to_owned(), Some(17179869184), Some(34359738368)), &blocks, &format) ==
+		"~sda2 size 16G -> 32G");
+	assert!(change_line(&Change::Remounted("sda1".to_owned(), "/boot".to_owned(), String::new()), &blocks, &format) ==
+		"~sda1 mountpoint /boot -> (none)");
+}
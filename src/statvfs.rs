@@ -0,0 +1,56 @@
+use std::ffi::CString;
+use std::mem;
+use libc;
+
+/// Space usage of a mounted filesystem, in bytes.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub struct FsUsage {
+	pub size : u64,
+	pub avail : u64,
+	pub used : u64,
+}
+
+impl FsUsage {
+	/// The percentage of the filesystem that is in use, rounded to the
+	/// nearest whole percent.
+	pub fn percent_used(&self) -> u64 {
+		(self.used * 100 + self.size / 2).checked_div(self.size).unwrap_or(0)
+	}
+}
+
+#[test]
+fn test_percent_used() {
+	assert!(FsUsage { size: 0, avail: 0, used: 0 }.percent_used() == 0);
+	assert!(FsUsage { size: 1000, avail: 1000, used: 0 }.percent_used() == 0);
+	assert!(FsUsage { size: 1000, avail: 650, used: 350 }.percent_used() == 35);
+	assert!(FsUsage { size: 1000, avail: 0, used: 1000 }.percent_used() == 100);
+	assert!(FsUsage { size: 3, avail: 1, used: 2 }.percent_used() == 67);
+}
+
+/// Calls `statvfs` on `mountpoint`. Any failure, such as a permission error
+/// or a stale NFS handle, gives `None`.
+pub fn fs_usage(mountpoint : &str) -> Option<FsUsage> {
+	let path = CString::new(mountpoint).ok()?;
+	let mut buf : libc::statvfs = unsafe { mem::zeroed() };
+	if unsafe { libc::statvfs(path.as_ptr(), &mut buf) } != 0 {
+		return None;
+	}
+
+	let frsize = buf.f_frsize as u64;
+	Some(FsUsage {
+		size: buf.f_blocks as u64 * frsize,
+		avail: buf.f_bavail as u64 * frsize,
+		used: (buf.f_blocks as u64).saturating_sub(buf.f_bfree as u64) * frsize,
+	})
+}
+
+#[test]
+fn test_fs_usage() {
+	let root = fs_usage("/").unwrap();
+	assert!(root.size > 0);
+	assert!(root.used <= root.size);
+	assert!(fs_usage("/nonexistent/lsblk/mountpoint").is_none());
+	assert!(fs_usage("nul\0byte").is_none());
+}
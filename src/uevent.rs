@@ -0,0 +1,108 @@
+use std::io;
+use std::mem;
+use libc;
+
+/// What happened to a block device, from a kernel uevent.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(PartialEq)]
+pub enum Action { Add, Remove }
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct DeviceEvent {
+	pub action : Action,
+	/// The kernel name, such as `sda1`.
+	pub name : String,
+}
+
+/// Reads a uevent, which is a header such as `add@/devices/...` followed by
+/// NUL-terminated `KEY=value` pairs. Events for anything other than block
+/// devices being added or removed give `None`.
+pub fn parse_uevent(message : &[u8]) -> Option<DeviceEvent> {
+	let mut action = None;
+	let mut subsystem = None;
+	let mut name = None;
+	for field in message.split(|&byte| byte == 0).skip(1) {
+		let field = String::from_utf8_lossy(field);
+		let mut pair = field.splitn(2, '=');
+		match (pair.next(), pair.next()) {
+			(Some("ACTION"), Some("add")) => action = Some(Action::Add),
+			(Some("ACTION"), Some("remove")) => action = Some(Action::Remove),
+			(Some("SUBSYSTEM"), Some(value)) => subsystem = Some(value.to_owned()),
+			(Some("DEVNAME"), Some(value)) => name = Some(value.to_owned()),
+			_ => (),
+		}
+	}
+	match subsystem {
+		Some(ref subsystem) if subsystem == "block" => Some(DeviceEvent { action: action?, name: name? }),
+		_ => None,
+	}
+}
+
+#[test]
+fn test_parse_uevent() {
+	let add = b"add@/devices/pci0000:00/0000:00:14.0/usb2/2-1/host6/target6:0:0/6:0:0:0/block/sdb/sdb1\0\
+		ACTION=add\0DEVPATH=/devices/pci0000:00/0000:00:14.0/usb2/2-1/host6/target6:0:0/6:0:0:0/block/sdb/sdb1\0\
+		SUBSYSTEM=block\0MAJOR=8\0MINOR=17\0DEVNAME=sdb1\0DEVTYPE=partition\0PARTN=1\0SEQNUM=4321\0";
+	assert!(parse_uevent(add) == Some(DeviceEvent { action: Action::Add, name: "sdb1".to_owned() }));
+
+	let remove = b"remove@/devices/virtual/block/loop0\0ACTION=remove\0SUBSYSTEM=block\0DEVNAME=loop0\0DEVTYPE=disk\0";
+	assert!(parse_uevent(remove) == Some(DeviceEvent { action: Action::Remove, name: "loop0".to_owned() }));
+
+	let change = b"change@/devices/virtual/block/loop0\0ACTION=change\0SUBSYSTEM=block\0DEVNAME=loop0\0";
+	assert!(parse_uevent(change).is_none());
+	let usb = b"add@/devices/pci0000:00/usb2/2-1\0ACTION=add\0SUBSYSTEM=usb\0DEVNAME=bus/usb/002/003\0";
+	assert!(parse_uevent(usb).is_none());
+	assert!(parse_uevent(b"").is_none());
+}
+
+/// The multicast group that the kernel sends uevents to. udev rebroadcasts
+/// them to another group once it has processed them.
+const KERNEL_EVENTS : u32 = 1;
+
+/// A netlink socket that receives the kernel's uevents.
+pub struct UeventSocket {
+	fd : libc::c_int,
+}
+
+impl UeventSocket {
+	pub fn open() -> io::Result<UeventSocket> {
+		let fd = unsafe { libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT) };
+		if fd < 0 {
+			return Err(io::Error::last_os_error());
+		}
+		let socket = UeventSocket { fd };
+
+		let mut addr : libc::sockaddr_nl = unsafe { mem::zeroed() };
+		addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+		addr.nl_groups = KERNEL_EVENTS;
+		let len = mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t;
+		if unsafe { libc::bind(fd, &addr as *const libc::sockaddr_nl as *const libc::sockaddr, len) } != 0 {
+			return Err(io::Error::last_os_error());
+		}
+		Ok(socket)
+	}
+
+	/// Waits for the next uevent.
+	pub fn recv(&self) -> io::Result<Vec<u8>> {
+		let mut buf = vec![0; 8192];
+		loop {
+			let len = unsafe { libc::recv(self.fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0) };
+			if len >= 0 {
+				buf.truncate(len as usize);
+				return Ok(buf);
+			}
+			let err = io::Error::last_os_error();
+			if err.kind() != io::ErrorKind::Interrupted {
+				return Err(err);
+			}
+		}
+	}
+}
+
+impl Drop for UeventSocket {
+	fn drop(&mut self) {
+		unsafe { libc::close(self.fd) };
+	}
+}
@@ -0,0 +1,53 @@
+use std::ffi::CStr;
+use std::mem;
+use std::ptr;
+use libc;
+
+/// Calls one of the reentrant `getpwuid_r`-style lookups, growing the buffer
+/// until the entry fits. `lookup` returns the entry's name, if there is one.
+fn lookup_name<F>(mut lookup : F) -> Option<String>
+	where F: FnMut(&mut [libc::c_char]) -> Result<Option<String>, libc::c_int> {
+	let mut buf = vec![0; 1024];
+	loop {
+		match lookup(&mut buf) {
+			Ok(name) => return name,
+			Err(libc::ERANGE) if buf.len() < 1 << 20 => buf.resize(buf.len() * 2, 0),
+			Err(_) => return None,
+		}
+	}
+}
+
+/// The name of the user `uid`, or the number itself when it has no entry in
+/// the user database.
+pub fn user_name(uid : u32) -> String {
+	lookup_name(|buf| unsafe {
+		let mut entry : libc::passwd = mem::zeroed();
+		let mut result = ptr::null_mut();
+		match libc::getpwuid_r(uid, &mut entry, buf.as_mut_ptr(), buf.len(), &mut result) {
+			0 if result.is_null() => Ok(None),
+			0 => Ok(Some(CStr::from_ptr(entry.pw_name).to_string_lossy().into_owned())),
+			err => Err(err),
+		}
+	}).unwrap_or_else(|| uid.to_string())
+}
+
+/// Like `user_name`, for the group `gid`.
+pub fn group_name(gid : u32) -> String {
+	lookup_name(|buf| unsafe {
+		let mut entry : libc::group = mem::zeroed();
+		let mut result = ptr::null_mut();
+		match libc::getgrgid_r(gid, &mut entry, buf.as_mut_ptr(), buf.len(), &mut result) {
+			0 if result.is_null() => Ok(None),
+			0 => Ok(Some(CStr::from_ptr(entry.gr_name).to_string_lossy().into_owned())),
+			err => Err(err),
+		}
+	}).unwrap_or_else(|| gid.to_string())
+}
+
+#[test]
+fn test_names() {
+	assert!(user_name(0) == "root");
+	assert!(group_name(0) == "root");
+	assert!(user_name(4000000000) == "4000000000");
+	assert!(group_name(4000000000) == "4000000000");
+}
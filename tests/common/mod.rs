@@ -0,0 +1,97 @@
+#![allow(dead_code)]
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use lsblk::Context;
+
+static NEXT_ROOT : AtomicUsize = AtomicUsize::new(0);
+
+/// A throwaway directory laid out like `/`, for building fake sysfs, procfs
+/// and udev trees. It is removed again when dropped.
+pub struct FakeRoot {
+	root : PathBuf,
+}
+
+impl FakeRoot {
+	pub fn new() -> FakeRoot {
+		let n = NEXT_ROOT.fetch_add(1, Ordering::SeqCst);
+		let root = env::temp_dir().join(format!("lsblk-test-{}-{}", process::id(), n));
+		let _ = fs::remove_dir_all(&root);
+
+		let fake = FakeRoot { root };
+		fake.dir("sys/block");
+		fake.dir("run/udev/data");
+		fake.file("proc/mounts", "");
+		fake.file("proc/swaps", "Filename\t\t\t\tType\t\tSize\tUsed\tPriority\n");
+		fake
+	}
+
+	pub fn path(&self, path : &str) -> PathBuf {
+		self.root.join(path)
+	}
+
+	pub fn ctx(&self) -> Context {
+		Context::with_root(&self.root)
+	}
+
+	pub fn dir(&self, path : &str) {
+		fs::create_dir_all(self.path(path)).unwrap();
+	}
+
+	pub fn file(&self, path : &str, contents : &str) {
+		let path = self.path(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		File::create(path).unwrap().write_all(contents.as_bytes()).unwrap();
+	}
+
+	/// Like `file`, for binary contents such as a fake device node.
+	pub fn bytes(&self, path : &str, contents : &[u8]) {
+		let path = self.path(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		File::create(path).unwrap().write_all(contents).unwrap();
+	}
+
+	pub fn symlink<P: AsRef<Path>>(&self, path : &str, target : P) {
+		let path = self.path(path);
+		fs::create_dir_all(path.parent().unwrap()).unwrap();
+		unix::fs::symlink(target, path).unwrap();
+	}
+
+	pub fn remove(&self, path : &str) {
+		let path = self.path(path);
+		if path.is_dir() {
+			fs::remove_dir_all(path).unwrap();
+		} else {
+			fs::remove_file(path).unwrap();
+		}
+	}
+
+	/// Creates a sysfs device directory with the attributes every block
+	/// device or partition has.
+	pub fn device(&self, path : &str, dev : &str, sectors : u64) {
+		self.file(&format!("{}/dev", path), &format!("{}\n", dev));
+		self.file(&format!("{}/size", path), &format!("{}\n", sectors));
+		self.file(&format!("{}/ro", path), "0\n");
+	}
+
+	/// Like `device`, but also marks the directory as a partition.
+	pub fn partition(&self, path : &str, dev : &str, sectors : u64) {
+		self.device(path, dev, sectors);
+		self.file(&format!("{}/partition", path), "1\n");
+	}
+}
+
+impl Drop for FakeRoot {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.root);
+	}
+}
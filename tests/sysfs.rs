@@ -0,0 +1,594 @@
+extern crate lsblk;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+mod common;
+
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use common::FakeRoot;
+use lsblk::LsblkError;
+use lsblk::MajorMinor;
+
+#[test]
+fn test_list_block_devices() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.file("sys/block/sda/removable", "0\n");
+	root.file("sys/block/sda/queue/rotational", "1\n");
+	root.file("sys/block/sda/queue/logical_block_size", "4096\n");
+	root.file("sys/block/sda/queue/physical_block_size", "4096\n");
+	root.file("sys/block/sda/queue/minimum_io_size", "4096\n");
+	root.file("sys/block/sda/queue/optimal_io_size", "0\n");
+	root.file("sys/block/sda/queue/scheduler", "mq-deadline kyber [bfq] none\n");
+	root.file("sys/block/sda/device/state", "running\n");
+	root.file("sys/block/sda/queue/discard_granularity", "4096\n");
+	root.file("sys/block/sda/queue/discard_max_bytes", "2147450880\n");
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.partition("sys/block/sda/sda2", "8:2", 67104768);
+	root.file("sys/block/sda/sda1/start", "2048\n");
+	root.file("sys/block/sda/alignment_offset", "0\n");
+	root.file("sys/block/sda/sda1/alignment_offset", "3584\n");
+	root.file("run/udev/data/b8:1", "E:ID_TYPE=disk\nE:ID_FS_TYPE=vfat\n");
+	root.file("proc/mounts", "/dev/sda1 /boot vfat rw 0 0\n/dev/sda1 /mnt/boot vfat ro 0 0\n");
+	root.file("proc/swaps", "Filename Type Size Used Priority\n/dev/sda2 partition 33552380 0 -2\n");
+
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	assert!(blocks.len() == 1);
+
+	let sda = &blocks[0];
+	assert!(sda.name == "sda");
+	assert!(sda.majmin == MajorMinor { major: 8, minor: 0 });
+	assert!(sda.removable == Some(0));
+	assert!(sda.size == Some(34359738368));
+	assert!(sda.readonly == Some(0));
+	assert!(sda.rotational == Some(1));
+	assert!(sda.logical_block_size == Some(4096));
+	assert!(sda.physical_block_size == Some(4096));
+	assert!(sda.alignment_offset == Some(0));
+	assert!(sda.minimum_io_size == Some(4096));
+	assert!(sda.optimal_io_size == Some(0));
+	assert!(sda.scheduler == Some("bfq".to_owned()));
+	assert!(sda.state == Some("running".to_owned()));
+	assert!(sda.discard_granularity == Some(4096));
+	assert!(sda.discard_max_bytes == Some(2147450880));
+	assert!(sda.metadata.is_none());
+	assert!(sda.mountpoint.is_empty());
+
+	let mut parts = sda.partitions.clone();
+	parts.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(parts.len() == 2);
+
+	assert!(parts[0].name == "sda1");
+	assert!(parts[0].majmin == MajorMinor { major: 8, minor: 1 });
+	assert!(parts[0].size == Some(1048576));
+	assert!(parts[0].start == Some(2048));
+	assert!(parts[0].alignment_offset == Some(3584));
+	assert!(parts[0].mountpoint == "/boot");
+	assert!(parts[0].mountpoints == ["/boot", "/mnt/boot"]);
+	assert!(parts[0].mount_options == "rw");
+	assert!(parts[0].metadata.as_ref().and_then(|meta| meta.id_fs_type.clone()) == Some("vfat".to_owned()));
+
+	assert!(parts[1].name == "sda2");
+	assert!(parts[1].start.is_none());
+	assert!(parts[1].alignment_offset.is_none());
+	assert!(parts[1].mountpoint == "[SWAP]");
+	assert!(parts[1].mountpoints == ["[SWAP]"]);
+	assert!(parts[1].metadata.is_none());
+}
+
+#[test]
+fn test_empty_sys_block() {
+	let root = FakeRoot::new();
+	assert!(lsblk::list_block_devices_in(&root.ctx()).unwrap().is_empty());
+}
+
+#[test]
+fn test_missing_sys_block() {
+	let root = FakeRoot::new();
+	root.remove("sys/block");
+	let err = lsblk::list_block_devices_in(&root.ctx()).unwrap_err();
+	assert!(err.to_string().contains("sys/block"));
+	match err {
+		LsblkError::Sysfs { path, .. } => assert!(path == root.path("sys/block")),
+		_ => panic!("expected a Sysfs error"),
+	}
+}
+
+#[test]
+fn test_iter_block_devices() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.device("sys/block/sdb", "8:16", 2048);
+
+	let first = lsblk::iter_block_devices_in(&root.ctx()).next().unwrap().unwrap();
+	assert!(first.name == "sda" || first.name == "sdb");
+	assert!(lsblk::iter_block_devices_in(&root.ctx()).filter_map(|block| block.ok()).any(|block| block.name == "sdb"));
+
+	root.remove("sys/block");
+	let mut blocks = lsblk::iter_block_devices_in(&root.ctx());
+	assert!(blocks.next().unwrap().unwrap_err().to_string().contains("sys/block"));
+	assert!(blocks.next().is_none());
+}
+
+#[test]
+fn test_swaps() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.device("sys/block/dm-0", "253:0", 2048);
+	root.file("sys/block/dm-0/dm/name", "vg-swap\n");
+	root.bytes("dev/dm-0", &[]);
+	root.symlink("dev/mapper/vg-swap", "../dm-0");
+	root.file("proc/swaps", "Filename Type Size Used Priority\n/dev/mapper/vg-swap partition 1020 0 -2\n");
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks[0].mountpoint == "[SWAP]");
+	assert!(blocks[1].mountpoint.is_empty());
+
+	root.remove("proc/swaps");
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	assert!(blocks.len() == 2);
+	assert!(blocks.iter().all(|block| block.mountpoint.is_empty()));
+}
+
+#[test]
+fn test_device_node() {
+	use std::fs;
+	use std::os::unix::fs::MetadataExt;
+	use std::os::unix::fs::PermissionsExt;
+
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.bytes("dev/sda", &[]);
+	fs::set_permissions(root.path("dev/sda"), fs::Permissions::from_mode(0o660)).unwrap();
+
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	let node = blocks[0].node.unwrap();
+	assert!(node.mode == 0o100660);
+	assert!(node.uid == fs::metadata(root.path("dev/sda")).unwrap().uid());
+	assert!(blocks[0].partitions[0].node.is_none());
+}
+
+#[test]
+fn test_dev_path() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.device("sys/block/sdb", "8:16", 2048);
+	root.device("sys/block/dm-0", "253:0", 2048);
+	root.file("sys/block/dm-0/dm/name", "vg-root\n");
+	root.device("sys/block/dm-1", "253:1", 2048);
+	root.file("sys/block/dm-1/dm/name", "vg-swap\n");
+	root.bytes("dev/sda", &[]);
+	root.bytes("dev/sda1", &[]);
+	root.bytes("dev/dm-0", &[]);
+	root.symlink("dev/mapper/vg-root", "../dm-0");
+	root.bytes("dev/dm-1", &[]);
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 4);
+	assert!(blocks[0].dev_path == Some(PathBuf::from("/dev/mapper/vg-root")));
+	assert!(blocks[1].dev_path == Some(PathBuf::from("/dev/dm-1")));
+	assert!(blocks[2].dev_path == Some(PathBuf::from("/dev/sda")));
+	assert!(blocks[2].partitions[0].dev_path == Some(PathBuf::from("/dev/sda1")));
+	assert!(blocks[3].dev_path.is_none());
+}
+
+#[test]
+fn test_resolve_device() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.symlink("sys/dev/block/8:0", "../../block/sda");
+	root.symlink("sys/dev/block/8:1", "../../block/sda/sda1");
+	root.bytes("dev/disk/by-label/boot", &[]);
+
+	let mut ctx = root.ctx();
+	ctx.device_number = |path| match path.file_name()?.to_str()? {
+		"boot" => Some(MajorMinor { major: 8, minor: 1 }),
+		"missing" => Some(MajorMinor { major: 8, minor: 2 }),
+		_ => None,
+	};
+	assert!(lsblk::resolve_device(&ctx, root.path("dev/disk/by-label/boot")).unwrap() == "sda1");
+
+	match lsblk::resolve_device(&ctx, "/dev/missing") {
+		Err(LsblkError::UnknownDevice(path)) => assert!(path == Path::new("/dev/missing")),
+		other => panic!("expected an unknown device, got {:?}", other),
+	}
+	let err = lsblk::resolve_device(&ctx, "/etc/passwd").unwrap_err();
+	assert!(err.to_string() == "/etc/passwd: not a block device");
+}
+
+#[test]
+fn test_probe() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 8);
+	let mut image = vec![0; 4096];
+	image[4086..4096].copy_from_slice(b"SWAPSPACE2");
+	image[1036..1052].copy_from_slice(&[0x11; 16]);
+	root.bytes("dev/sda", &image);
+
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	assert!(blocks[0].metadata.is_none());
+
+	let mut ctx = root.ctx();
+	ctx.probe = true;
+	let blocks = lsblk::list_block_devices_in(&ctx).unwrap();
+	let meta = blocks[0].metadata.as_ref().unwrap();
+	assert!(meta.id_fs_type == Some("swap".to_owned()));
+	assert!(meta.id_fs_uuid == Some("11111111-1111-1111-1111-111111111111".to_owned()));
+
+	// udev's properties take precedence, once a fresh context reads them.
+	root.file("run/udev/data/b8:0", "E:ID_TYPE=disk\nE:ID_FS_TYPE=crypto_LUKS\n");
+	let blocks = lsblk::list_block_devices_in(&ctx).unwrap();
+	assert!(blocks[0].metadata.as_ref().unwrap().id_fs_type == Some("swap".to_owned()));
+	let mut ctx = root.ctx();
+	ctx.probe = true;
+	let blocks = lsblk::list_block_devices_in(&ctx).unwrap();
+	assert!(blocks[0].metadata.as_ref().unwrap().id_fs_type == Some("crypto_LUKS".to_owned()));
+}
+
+#[test]
+fn test_probe_size() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 8);
+	root.device("sys/block/dm-0", "253:0", 0);
+	root.device("sys/block/dm-1", "253:1", 0);
+	root.bytes("dev/dm-0", &[]);
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.iter().map(|block| block.size).collect::<Vec<_>>() == [Some(0), Some(0), Some(4096)]);
+
+	let mut ctx = root.ctx();
+	ctx.probe = true;
+	ctx.device_size = |_| Ok(1073741824);
+	let mut blocks = lsblk::list_block_devices_in(&ctx).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.iter().map(|block| block.size).collect::<Vec<_>>() == [Some(1073741824), Some(0), Some(4096)]);
+}
+
+#[test]
+fn test_device_mapper() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 67106816);
+	root.symlink("sys/block/sda/sda1/holders/dm-0", "../../../dm-0");
+	root.device("sys/block/dm-0", "253:0", 67104768);
+	root.file("sys/block/dm-0/dm/name", "vg-root\n");
+	root.symlink("sys/block/dm-0/slaves/sda1", "../../sda/sda1");
+	root.device("sys/block/dm-1", "253:1", 2048);
+	root.file("sys/block/dm-1/dm/name", "vg-swap\n");
+	root.file("proc/mounts", "/dev/mapper/vg-root / ext4 rw,relatime 0 0\n/dev/dm-0 /srv ext4 rw 0 0\n");
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 3);
+
+	assert!(blocks[0].name == "dm-0");
+	assert!(blocks[0].dm_name == Some("vg-root".to_owned()));
+	assert!(blocks[0].slaves == ["sda1"]);
+	assert!(blocks[0].holders.is_empty());
+	assert!(blocks[0].mountpoints == ["/"]);
+	assert!(blocks[0].mount_options == "rw,relatime");
+
+	assert!(blocks[1].name == "dm-1");
+	assert!(blocks[1].dm_name == Some("vg-swap".to_owned()));
+	assert!(blocks[1].state.is_none());
+	assert!(blocks[1].slaves.is_empty());
+	assert!(blocks[1].mountpoint.is_empty());
+
+	assert!(blocks[2].name == "sda");
+	assert!(blocks[2].dm_name.is_none());
+	assert!(blocks[2].slaves.is_empty());
+	assert!(blocks[2].partitions[0].holders == ["dm-0"]);
+}
+
+#[test]
+fn test_symlinked_block_entries() {
+	let root = FakeRoot::new();
+	let host = "sys/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0";
+	let sda = format!("{}/block/sda", host);
+	root.device(&sda, "8:0", 67108864);
+	root.partition(&format!("{}/sda1", sda), "8:1", 67106816);
+	root.symlink(&format!("{}/device", sda), "../../../0:0:0:0");
+	root.file(&format!("{}/state", host), "running\n");
+	root.symlink(&format!("{}/sda1/holders/dm-0", sda), "../../../../../../../../../../virtual/block/dm-0");
+	root.symlink("sys/block/sda", format!("../devices/{}", &sda["sys/devices/".len()..]));
+	let dm = "sys/devices/virtual/block/dm-0";
+	root.device(dm, "253:0", 67104768);
+	root.file(&format!("{}/dm/name", dm), "vg-root\n");
+	root.symlink(&format!("{}/slaves/sda1", dm), format!("../../../../{}/sda1", &sda["sys/devices/".len()..]));
+	root.symlink("sys/block/dm-0", "../devices/virtual/block/dm-0");
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 2);
+	assert!(blocks[0].name == "dm-0");
+	assert!(blocks[0].dm_name == Some("vg-root".to_owned()));
+	assert!(blocks[0].slaves == ["sda1"]);
+	assert!(blocks[1].name == "sda");
+	assert!(blocks[1].state == Some("running".to_owned()));
+	assert!(blocks[1].partitions.len() == 1);
+	assert!(blocks[1].partitions[0].holders == ["dm-0"]);
+}
+
+#[test]
+fn test_list_block_devices_by_number() {
+	let root = FakeRoot::new();
+	let sda = "sys/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda";
+	root.device(sda, "8:0", 67108864);
+	root.partition(&format!("{}/sda1", sda), "8:1", 2048);
+	root.partition(&format!("{}/sda2", sda), "8:2", 67104768);
+	root.symlink(&format!("{}/sda2/holders/dm-0", sda), "../../../../../../../../../../virtual/block/dm-0");
+	let dm = "sys/devices/virtual/block/dm-0";
+	root.device(dm, "253:0", 67104768);
+	root.file(&format!("{}/dm/name", dm), "vg-root\n");
+	root.symlink(&format!("{}/slaves/sda2", dm), format!("../../../../{}/sda2", &sda["sys/devices/".len()..]));
+	root.partition("sys/devices/virtual/block/loop0/loop0p1", "259:0", 1024);
+
+	root.symlink("sys/dev/block/8:0", format!("../../devices/{}", &sda["sys/devices/".len()..]));
+	root.symlink("sys/dev/block/8:1", format!("../../devices/{}/sda1", &sda["sys/devices/".len()..]));
+	root.symlink("sys/dev/block/8:2", format!("../../devices/{}/sda2", &sda["sys/devices/".len()..]));
+	root.symlink("sys/dev/block/253:0", "../../devices/virtual/block/dm-0");
+	root.symlink("sys/dev/block/259:0", "../../devices/virtual/block/loop0/loop0p1");
+	root.symlink("sys/dev/block/8:16", "../../devices/virtual/block/sdb");
+
+	// Nothing is linked from /sys/block, so the scan there finds nothing.
+	assert!(lsblk::list_block_devices_in(&root.ctx()).unwrap().is_empty());
+
+	let mut blocks = lsblk::list_block_devices_by_number_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 2);
+	assert!(blocks[0].name == "dm-0");
+	assert!(blocks[0].majmin == MajorMinor { major: 253, minor: 0 });
+	assert!(blocks[0].slaves == ["sda2"]);
+	assert!(blocks[1].name == "sda");
+	let mut parts = blocks[1].partitions.clone();
+	parts.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(parts.len() == 2);
+	assert!(parts[0].name == "sda1");
+	assert!(parts[0].majmin == MajorMinor { major: 8, minor: 1 });
+	assert!(parts[1].name == "sda2");
+	assert!(parts[1].holders == ["dm-0"]);
+
+	root.remove("sys/dev/block");
+	let err = lsblk::list_block_devices_by_number_in(&root.ctx()).unwrap_err();
+	assert!(err.to_string().contains("sys/dev/block"));
+}
+
+#[test]
+fn test_partition_attribute() {
+	let root = FakeRoot::new();
+	root.device("sys/block/mmcblk0", "179:0", 2048);
+	root.partition("sys/block/mmcblk0/mmcblk0p1", "179:1", 1024);
+	root.device("sys/block/mmcblk0/mmcblk0boot0", "179:8", 1024);
+	root.file("sys/block/mmcblk0/power/control", "auto\n");
+
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	assert!(blocks.len() == 1);
+	assert!(blocks[0].partitions.len() == 1);
+	assert!(blocks[0].partitions[0].name == "mmcblk0p1");
+}
+
+#[test]
+fn test_loop_backing_file() {
+	let root = FakeRoot::new();
+	root.device("sys/block/loop0", "7:0", 2048);
+	root.file("sys/block/loop0/loop/backing_file", "/srv/images/disk.img\n");
+	root.device("sys/block/loop1", "7:1", 0);
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 2);
+	assert!(blocks[0].backing_file == Some("/srv/images/disk.img".to_owned()));
+	assert!(blocks[1].backing_file.is_none());
+}
+
+#[test]
+fn test_hotplug() {
+	let root = FakeRoot::new();
+	let sda = "sys/devices/pci0000:00/0000:00:17.0/ata1/host0/target0:0:0/0:0:0:0/block/sda";
+	root.device(sda, "8:0", 2048);
+	root.symlink("sys/block/sda", format!("../../{}", sda));
+	let usb = "sys/devices/pci0000:00/0000:00:14.0/usb1/1-1";
+	let sdb = format!("{}/1-1:1.0/host6/target6:0:0/6:0:0:0/block/sdb", usb);
+	root.device(&sdb, "8:16", 2048);
+	root.file(&format!("{}/removable", usb), "removable\n");
+	root.symlink("sys/block/sdb", format!("../../{}", sdb));
+	let mmc = "sys/devices/platform/mmc0/mmc0:0001/block/mmcblk0";
+	root.device(mmc, "179:0", 2048);
+	root.dir("sys/bus/sdio");
+	root.symlink("sys/devices/platform/mmc0/subsystem", "../../../bus/sdio");
+	root.symlink("sys/block/mmcblk0", format!("../../{}", mmc));
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 3);
+	assert!(blocks[0].name == "mmcblk0");
+	assert!(blocks[0].hotplug == Some(1));
+	assert!(blocks[1].name == "sda");
+	assert!(blocks[1].hotplug == Some(0));
+	assert!(blocks[2].name == "sdb");
+	assert!(blocks[2].hotplug == Some(1));
+}
+
+#[test]
+fn test_dax() {
+	let root = FakeRoot::new();
+	root.device("sys/block/pmem0", "259:0", 2048);
+	root.file("sys/block/pmem0/queue/dax", "1\n");
+	root.device("sys/block/sda", "8:0", 2048);
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 2);
+	assert!(blocks[0].dax == Some(1));
+	assert!(blocks[1].dax.is_none());
+}
+
+#[test]
+fn test_multi_device_filesystems() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 2048);
+	root.partition("sys/block/sda/sda1", "8:1", 1024);
+	root.device("sys/block/sdb", "8:16", 2048);
+	root.device("sys/block/sdc", "8:32", 2048);
+	root.device("sys/block/sdd", "8:48", 2048);
+	let btrfs = "E:ID_TYPE=disk\nE:ID_FS_TYPE=btrfs\nE:ID_FS_UUID=0b56138b-6124-4ec4-a7a3-7c503516a65c\n";
+	root.file("run/udev/data/b8:1", btrfs);
+	root.file("run/udev/data/b8:16", btrfs);
+	root.file("run/udev/data/b8:32", "E:ID_TYPE=disk\nE:ID_FS_TYPE=btrfs\nE:ID_FS_UUID=9a0e3f4c-7d8b-4b5e-8f6a-1c2d3e4f5a6b\n");
+	root.file("run/udev/data/b8:48", "E:ID_TYPE=disk\nE:ID_FS_TYPE=ext4\nE:ID_FS_UUID=0b56138b-6124-4ec4-a7a3-7c503516a65c\n");
+
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	let filesystems = lsblk::multi_device_filesystems(&blocks);
+	assert!(filesystems.len() == 1);
+	assert!(filesystems["0b56138b-6124-4ec4-a7a3-7c503516a65c"] == ["sda1", "sdb"]);
+}
+
+#[test]
+fn test_md_raid() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 2048);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.symlink("sys/block/sda/sda1/holders/md0", "../../../md0");
+	root.device("sys/block/sdb", "8:16", 2048);
+	root.partition("sys/block/sdb/sdb1", "8:17", 2048);
+	root.symlink("sys/block/sdb/sdb1/holders/md0", "../../../md0");
+	root.device("sys/block/md0", "9:0", 2048);
+	root.file("sys/block/md0/md/level", "raid1\n");
+	root.symlink("sys/block/md0/slaves/sda1", "../../sda/sda1");
+	root.symlink("sys/block/md0/slaves/sdb1", "../../sdb/sdb1");
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks.len() == 3);
+	assert!(blocks[0].name == "md0");
+	assert!(blocks[0].raid_level == Some("raid1".to_owned()));
+	let mut slaves = blocks[0].slaves.clone();
+	slaves.sort();
+	assert!(slaves == ["sda1", "sdb1"]);
+	assert!(blocks[1].raid_level.is_none());
+	assert!(blocks[1].partitions[0].holders == ["md0"]);
+}
+
+#[test]
+fn test_mountinfo() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.file("proc/self/mountinfo", concat!(
+		"41 29 8:1 /www /var/www rw,nosuid shared:2 - ext4 /dev/sda1 rw\n",
+		"30 29 8:1 / /srv rw,relatime shared:1 - ext4 /dev/sda1 rw,errors=remount-ro\n"));
+	root.file("proc/mounts", "/dev/sda1 /ignored ext4 rw 0 0\n");
+
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	let sda1 = &blocks[0].partitions[0];
+	assert!(sda1.mountpoint == "/srv");
+	assert!(sda1.mountpoints == ["/srv", "/var/www"]);
+	assert!(sda1.mount_options == "rw,relatime,errors=remount-ro");
+}
+
+#[test]
+fn test_mounted_whole_disk() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.device("sys/block/sdb", "8:16", 67108864);
+	root.device("sys/block/sdc", "8:32", 2097152);
+	root.file("proc/self/mountinfo", concat!(
+		"30 29 8:1 / /boot rw,relatime shared:1 - vfat /dev/sda1 rw\n",
+		"31 29 8:16 / /data rw,noatime shared:2 - ext4 /dev/sdb rw\n"));
+	root.file("proc/swaps", "Filename Type Size Used Priority\n/dev/sdc partition 1048572 0 -2\n");
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks[0].mountpoint.is_empty());
+	assert!(blocks[0].partitions[0].mountpoint == "/boot");
+	assert!(blocks[1].mountpoint == "/data");
+	assert!(blocks[1].mountpoints == ["/data"]);
+	assert!(blocks[1].mount_options == "rw,noatime");
+	assert!(blocks[2].mountpoint == "[SWAP]");
+}
+
+#[test]
+fn test_mount_by_symlink() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.partition("sys/block/sda/sda2", "8:2", 2048);
+	root.bytes("dev/sda1", &[]);
+	root.bytes("dev/sda2", &[]);
+	root.symlink("dev/disk/by-uuid/0a1b2c3d-1111-2222-3333-444455556666", "../../sda1");
+	root.file("proc/mounts", concat!(
+		"/dev/disk/by-uuid/0a1b2c3d-1111-2222-3333-444455556666 /boot ext4 rw 0 0\n",
+		"/dev/sda1 /mnt/boot ext4 rw 0 0\n",
+		"/dev/disk/by-uuid/missing /mnt/missing ext4 rw 0 0\n"));
+
+	let mut blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+	blocks[0].partitions.sort_by(|a, b| a.name.cmp(&b.name));
+	assert!(blocks[0].partitions[0].mountpoint == "/boot");
+	assert!(blocks[0].partitions[0].mountpoints == ["/boot", "/mnt/boot"]);
+	assert!(blocks[0].partitions[1].mountpoints.is_empty());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_round_trip() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.file("run/udev/data/b8:1", "E:ID_TYPE=disk\nE:ID_FS_TYPE=ext4\n");
+	root.file("proc/mounts", "/dev/sda1 /boot ext4 rw 0 0\n");
+	let blocks = lsblk::list_block_devices_in(&root.ctx()).unwrap();
+
+	let json = serde_json::to_string(&blocks).unwrap();
+	assert!(json.contains("\"majmin\":\"8:0\""));
+	assert!(json.contains("\"majmin\":\"8:1\""));
+	let restored : Vec<lsblk::Block> = serde_json::from_str(&json).unwrap();
+	assert!(restored == blocks);
+
+	assert!(serde_json::from_str::<MajorMinor>("\"8:x\"").unwrap_err().to_string().contains("invalid device number '8:x'"));
+}
+
+static UDEV_READS : AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn test_udev_data_cached_across_scans() {
+	let root = FakeRoot::new();
+	root.device("sys/block/sda", "8:0", 67108864);
+	root.partition("sys/block/sda/sda1", "8:1", 2048);
+	root.file("run/udev/data/b8:0", "E:ID_TYPE=disk\n");
+	root.file("run/udev/data/b8:1", "E:ID_TYPE=disk\nE:ID_FS_TYPE=ext4\n");
+	root.symlink("sys/dev/block/8:0", "../../block/sda");
+	root.symlink("sys/dev/block/8:1", "../../block/sda/sda1");
+	let mut ctx = root.ctx();
+	ctx.read_udev_data = |path| {
+		UDEV_READS.fetch_add(1, Ordering::SeqCst);
+		std::fs::read_to_string(path)
+	};
+
+	let blocks = lsblk::iter_block_devices_in(&ctx).collect::<Result<Vec<_>, _>>().unwrap();
+	assert!(blocks[0].partitions[0].metadata.as_ref().and_then(|meta| meta.id_fs_type.clone()) == Some("ext4".to_owned()));
+	assert!(UDEV_READS.load(Ordering::SeqCst) == 2);
+
+	// Later scans through the same context, by any entry point, read nothing.
+	let blocks = lsblk::list_block_devices_in(&ctx).unwrap();
+	assert!(blocks[0].partitions[0].metadata.as_ref().and_then(|meta| meta.id_fs_type.clone()) == Some("ext4".to_owned()));
+	lsblk::list_block_devices_by_number_in(&ctx).unwrap();
+	lsblk::iter_block_devices_in(&ctx.clone()).count();
+	assert!(UDEV_READS.load(Ordering::SeqCst) == 2);
+
+	// A fresh context reads again.
+	let mut fresh = root.ctx();
+	fresh.read_udev_data = ctx.read_udev_data;
+	lsblk::list_block_devices_in(&fresh).unwrap();
+	assert!(UDEV_READS.load(Ordering::SeqCst) == 4);
+}